@@ -0,0 +1,116 @@
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+use regez::nfa::DFA_STATE_BUDGET;
+
+/// Compiles a pattern to a matcher at compile time.
+///
+/// `regez!("[a-z]+@[a-z]+")` expands to a `fn(&[u8]) -> bool` telling whether
+/// the whole input is matched. The generated code has no dependencies and
+/// an invalid pattern is reported as a compile error.
+#[proc_macro]
+pub fn regez(input: TokenStream) -> TokenStream {
+    let literal = match single_literal(input) {
+        Ok(literal) => literal,
+        Err(span) => return compile_error("expected a string literal", span),
+    };
+
+    let Some(pattern) = unquote(&literal.to_string()) else {
+        return compile_error("expected a string literal", literal.span());
+    };
+
+    let ast = match regez::parse(&pattern) {
+        Ok(ast) => ast,
+        Err(err) => return compile_error(&err.report(&pattern), literal.span()),
+    };
+
+    let Some(dfa) = regez::determinize_within(&ast, DFA_STATE_BUDGET) else {
+        let message = format!("the DFA of this pattern needs more than {DFA_STATE_BUDGET} states");
+        return compile_error(&message, literal.span());
+    };
+
+    let dfa = dfa.minimize();
+
+    let code = dfa.compile_rust().unwrap();
+
+    format!("{{ {code} is_match }}").parse().unwrap()
+}
+
+fn single_literal(input: TokenStream) -> Result<Literal, Span> {
+    let mut tokens = input.into_iter();
+
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => Ok(literal),
+        // Literals forwarded by `macro_rules!` come wrapped in an invisible group
+        (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::None => {
+            single_literal(group.stream())
+        }
+        (Some(token), _) => Err(token.span()),
+        (None, _) => Err(Span::call_site()),
+    }
+}
+
+/// Returns the value of a string literal, raw or not, from its source text.
+fn unquote(source: &str) -> Option<String> {
+    if let Some(raw) = source.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let raw = &raw[hashes..raw.len().checked_sub(hashes)?];
+
+        return Some(raw.strip_prefix('"')?.strip_suffix('"')?.to_string());
+    }
+
+    let mut chars = source
+        .strip_prefix('"')?
+        .strip_suffix('"')?
+        .chars()
+        .peekable();
+    let mut res = String::new();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            'n' => res.push('\n'),
+            'r' => res.push('\r'),
+            't' => res.push('\t'),
+            '0' => res.push('\0'),
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                res.push(char::from(u8::from_str_radix(&digits, 16).ok()?));
+            }
+            'u' => {
+                let digits: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let code = u32::from_str_radix(digits.strip_prefix('{')?, 16).ok()?;
+                res.push(char::from_u32(code)?);
+            }
+            // A line continuation skips the newline and the indentation
+            '\n' => while chars.next_if(|c| c.is_whitespace()).is_some() {},
+            c => res.push(c),
+        }
+    }
+
+    Some(res)
+}
+
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+
+    let tokens: Vec<TokenTree> = vec![
+        Ident::new("compile_error", span).into(),
+        {
+            let mut bang = Punct::new('!', Spacing::Alone);
+            bang.set_span(span);
+            bang.into()
+        },
+        {
+            let mut group = Group::new(Delimiter::Parenthesis, TokenTree::from(message).into());
+            group.set_span(span);
+            group.into()
+        },
+    ];
+
+    tokens.into_iter().collect()
+}
@@ -0,0 +1,69 @@
+use regez::{build, dfa::Dfa};
+use regez_macros::regez;
+
+/// Every string of up to `max_len` bytes of `alphabet`.
+fn strings(alphabet: &[u8], max_len: usize) -> Vec<Vec<u8>> {
+    let mut res = vec![Vec::new()];
+    let mut start = 0;
+
+    for _ in 0..max_len {
+        let end = res.len();
+
+        for i in start..end {
+            for &c in alphabet {
+                let mut string = res[i].clone();
+                string.push(c);
+                res.push(string);
+            }
+        }
+
+        start = end;
+    }
+
+    res
+}
+
+macro_rules! check {
+    ($($pattern:literal),* $(,)?) => {
+        $({
+            let matcher: fn(&[u8]) -> bool = regez!($pattern);
+            let dfa = Dfa::from_nfa(&build($pattern).unwrap());
+
+            for input in strings(b"abcx@.\xff", 5) {
+                assert_eq!(matcher(&input), dfa.is_match(&input), "{} on {input:?}", $pattern);
+            }
+        })*
+    };
+}
+
+#[test]
+fn test_against_dfa() {
+    check!(
+        "()",
+        "[]",
+        "a",
+        "(a|b)*abb",
+        "[a-c]+@[a-c]+\\.x",
+        "(a|b)*a(a|b){3}",
+        "(ab|c){1,3}x{2,}",
+        "[a-c]+&~(ab|ba)",
+        "~(.*ab.*)",
+        "\\xff+|x",
+    );
+}
+
+#[test]
+fn test_raw_and_escaped_literals() {
+    let raw = regez!(r"a\.b");
+    let escaped = regez!("a\\.b");
+    let hex = regez!("\x61+");
+
+    assert!(raw(b"a.b") && !raw(b"axb"));
+    assert!(escaped(b"a.b") && !escaped(b"axb"));
+    assert!(hex(b"aaa") && !hex(b""));
+}
+
+#[test]
+fn test_compile_errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
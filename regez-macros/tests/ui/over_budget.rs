@@ -0,0 +1,5 @@
+use regez_macros::regez;
+
+fn main() {
+    let _ = regez!("(a|b)*a(a|b){15}");
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+};
+
+use petgraph::{
+    algo::{is_cyclic_directed, toposort},
+    visit::EdgeRef,
+    Direction,
+};
+
+use crate::{
+    dfa::Dfa,
+    escape::label_byte,
+    nfa::{Nfa, State},
+};
+
+/// Facts about the language of a minimized DFA.
+pub struct Analysis {
+    pub states: usize,
+    pub transitions: usize,
+    pub byte_classes: usize,
+    pub finite: bool,
+    /// Length of the shortest match, `None` when the language is empty
+    pub min_len: Option<usize>,
+    /// Length of the longest match, `None` when the language is empty or infinite
+    pub max_len: Option<usize>,
+}
+
+impl Analysis {
+    /// Expects the output of `minimize`, which only keeps states that lie on
+    /// a path to an accepting state, so any cycle can be pumped.
+    pub fn new(dfa: &Nfa) -> Analysis {
+        let finite = !is_cyclic_directed(&dfa.graph);
+
+        Analysis {
+            states: dfa.graph.node_count(),
+            transitions: dfa.graph.edge_count(),
+            byte_classes: Dfa::from_nfa(dfa).class_count,
+            finite,
+            min_len: min_len(dfa),
+            max_len: if finite { max_len(dfa) } else { None },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_len.is_none()
+    }
+}
+
+fn min_len(dfa: &Nfa) -> Option<usize> {
+    let mut distances = vec![None; dfa.graph.node_count()];
+    let mut work_list = VecDeque::from([dfa.start]);
+    distances[dfa.start.index()] = Some(0);
+
+    while let Some(node) = work_list.pop_front() {
+        let distance = distances[node.index()].unwrap();
+
+        if dfa.graph[node] == State::Accepting {
+            return Some(distance);
+        }
+
+        for next in dfa.graph.neighbors_directed(node, Direction::Outgoing) {
+            if distances[next.index()].is_none() {
+                distances[next.index()] = Some(distance + 1);
+                work_list.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+/// Longest path from the start to an accepting state, the graph being acyclic.
+fn max_len(dfa: &Nfa) -> Option<usize> {
+    let mut distances: Vec<Option<usize>> = vec![None; dfa.graph.node_count()];
+    distances[dfa.start.index()] = Some(0);
+
+    for node in toposort(&dfa.graph, None).unwrap() {
+        let Some(distance) = distances[node.index()] else {
+            continue;
+        };
+
+        for next in dfa.graph.neighbors_directed(node, Direction::Outgoing) {
+            let longest = &mut distances[next.index()];
+            *longest = Some(longest.map_or(distance + 1, |d| d.max(distance + 1)));
+        }
+    }
+
+    dfa.graph
+        .node_indices()
+        .filter(|&node| dfa.graph[node] == State::Accepting)
+        .filter_map(|node| distances[node.index()])
+        .max()
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+        writeln!(f, "empty: {}", yes_no(self.is_empty()))?;
+        writeln!(f, "finite: {}", yes_no(self.finite))?;
+
+        match self.min_len {
+            Some(len) => writeln!(f, "min length: {len}")?,
+            None => writeln!(f, "min length: -")?,
+        }
+
+        match (self.min_len, self.max_len) {
+            (None, _) => writeln!(f, "max length: -")?,
+            (Some(_), Some(len)) => writeln!(f, "max length: {len}")?,
+            (Some(_), None) => writeln!(f, "max length: infinite")?,
+        }
+
+        writeln!(f, "states: {}", self.states)?;
+        writeln!(f, "transitions: {}", self.transitions)?;
+        write!(f, "byte classes: {}", self.byte_classes)
+    }
+}
+
+/// Bytes of typical text and code, most frequent first.
+const COMMON_BYTES: &[u8] =
+    b" etaoinsrhldcumfpgwybvkxjqz\n_.,;()=ETAOINSRHLDCUMFPGWYBVKXJQZ0123456789";
+
+/// How common `c` is in typical input, lower being more common.
+fn byte_rank(c: u8) -> usize {
+    COMMON_BYTES
+        .iter()
+        .position(|&common| common == c)
+        .unwrap_or(COMMON_BYTES.len())
+}
+
+/// Where matches of a minimized DFA can start, telling a search which
+/// positions it can skip without running the automaton.
+pub struct Literals {
+    /// Bytes every match starts with
+    pub prefix: Vec<u8>,
+    /// Bytes a match can start with, `None` when the empty string matches
+    pub first_bytes: Option<Vec<u8>>,
+}
+
+impl Literals {
+    pub fn new(dfa: &Nfa) -> Literals {
+        let mut prefix = Vec::new();
+        let mut node = dfa.start;
+
+        // Follow states with a single way out on a single byte
+        while dfa.graph[node] == State::NotAccepting && prefix.len() < dfa.graph.node_count() {
+            let mut edges = dfa.graph.edges_directed(node, Direction::Outgoing);
+
+            let (Some(edge), None) = (edges.next(), edges.next()) else {
+                break;
+            };
+
+            match edge.weight().ranges()[..] {
+                [(a, b)] if a == b => prefix.push(a),
+                _ => break,
+            }
+
+            node = edge.target();
+        }
+
+        let first_bytes = match dfa.graph[dfa.start] {
+            State::Accepting => None,
+            State::NotAccepting => {
+                let mut bytes: Vec<u8> = dfa
+                    .graph
+                    .edges_directed(dfa.start, Direction::Outgoing)
+                    .flat_map(|edge| edge.weight().ranges())
+                    .flat_map(|(a, b)| a..=b)
+                    .collect();
+                bytes.sort();
+                Some(bytes)
+            }
+        };
+
+        Literals {
+            prefix,
+            first_bytes,
+        }
+    }
+
+    /// Byte of the prefix least likely to occur in the input, with its offset.
+    pub fn rare_byte(&self) -> Option<(usize, u8)> {
+        self.prefix
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by_key(|&(i, c)| (byte_rank(c), std::cmp::Reverse(i)))
+    }
+}
+
+impl Display for Literals {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let quoted = |bytes: &[u8]| {
+            let escaped: String = bytes.iter().map(|&c| label_byte(c)).collect();
+            format!("\"{escaped}\"")
+        };
+
+        writeln!(f, "prefix: {}", quoted(&self.prefix))?;
+
+        match &self.first_bytes {
+            Some(bytes) => writeln!(f, "first bytes: {}", quoted(bytes))?,
+            None => writeln!(f, "first bytes: any, the empty string matches")?,
+        }
+
+        match self.rare_byte() {
+            Some((i, c)) => write!(f, "rare byte: {} at offset {i}", quoted(&[c])),
+            None => write!(f, "rare byte: -"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Analysis;
+    use crate::build;
+
+    #[test]
+    fn test_analysis() {
+        // Pattern, empty, finite, min and max lengths, states, transitions
+        // and byte classes
+        let cases = [
+            ("[]", true, true, None, None, 1, 0, 1),
+            ("abc", false, true, Some(3), Some(3), 4, 3, 4),
+            ("a(b|cd)?", false, true, Some(1), Some(3), 4, 4, 5),
+            ("(ab)*", false, false, Some(0), None, 2, 2, 3),
+            ("[a-z]+@[a-z]+", false, false, Some(3), None, 4, 5, 3),
+            ("[ab]{2}&~(.*a.*)", false, true, Some(2), Some(2), 3, 2, 2),
+            ("a+&b+", true, true, None, None, 1, 0, 1),
+        ];
+
+        for (pattern, empty, finite, min_len, max_len, states, transitions, classes) in cases {
+            let analysis = Analysis::new(&build(pattern).unwrap());
+
+            assert_eq!(analysis.is_empty(), empty, "{pattern}");
+            assert_eq!(analysis.finite, finite, "{pattern}");
+            assert_eq!(analysis.min_len, min_len, "{pattern}");
+            assert_eq!(analysis.max_len, max_len, "{pattern}");
+            assert_eq!(analysis.states, states, "{pattern}");
+            assert_eq!(analysis.transitions, transitions, "{pattern}");
+            assert_eq!(analysis.byte_classes, classes, "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let analysis = Analysis::new(&build("(ab)*").unwrap());
+
+        assert_eq!(
+            analysis.to_string(),
+            "empty: no\nfinite: no\nmin length: 0\nmax length: infinite\n\
+             states: 2\ntransitions: 2\nbyte classes: 3"
+        );
+    }
+}
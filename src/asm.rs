@@ -0,0 +1,194 @@
+use std::fmt::{Error, Write};
+
+use petgraph::{graph::NodeIndex, visit::IntoNodeReferences};
+
+use crate::nfa::{densest_target, search_runs, Nfa, RunSearch, RunTest, State};
+
+fn asm_label(target: Option<NodeIndex>) -> String {
+    match target {
+        Some(state) => format!(".Ls{}", state.index()),
+        None => ".Lreject".to_string(),
+    }
+}
+
+impl Nfa {
+    /// Emits GNU assembler for x86-64 System V implementing
+    /// `int is_match(const unsigned char *input, size_t len)`, which tells
+    /// whether the whole input is matched. Expects the output of `minimize`.
+    pub fn compile_asm(&self) -> Result<String, Error> {
+        let live = self.live_states();
+        let mut res = String::new();
+        let mut labels = 0;
+
+        res.push_str("# int is_match(const unsigned char *input, size_t len)\n");
+        res.push_str("\t.text\n");
+        res.push_str("\t.globl\tis_match\n");
+        res.push_str("\t.type\tis_match, @function\n");
+        res.push_str("is_match:\n");
+        res.push_str("\taddq\t%rdi, %rsi\n");
+        writeln!(&mut res, "\tjmp\t.Ls{}", self.start.index())?;
+
+        for (index, state) in self.graph.node_references() {
+            writeln!(&mut res, ".Ls{}:", index.index())?;
+
+            let done = match state {
+                State::Accepting => ".Laccept",
+                State::NotAccepting => ".Lreject",
+            };
+
+            res.push_str("\tcmpq\t%rsi, %rdi\n");
+            writeln!(&mut res, "\tje\t{done}")?;
+            res.push_str("\tmovzbl\t(%rdi), %eax\n");
+            res.push_str("\tincq\t%rdi\n");
+
+            let runs = self.byte_runs(index, &live);
+            let fallthrough = densest_target(&runs);
+            let cases: Vec<(u8, u8, Option<NodeIndex>)> = runs
+                .into_iter()
+                .filter(|&(_, _, target)| target != fallthrough)
+                .collect();
+
+            if !cases.is_empty() {
+                let mut search = AsmSearch {
+                    s: &mut res,
+                    fallthrough,
+                    labels: &mut labels,
+                };
+
+                search_runs(&mut search, &cases, 0, 255)?;
+            }
+
+            writeln!(&mut res, "\tjmp\t{}", asm_label(fallthrough))?;
+        }
+
+        res.push_str(".Lreject:\n");
+        res.push_str("\txorl\t%eax, %eax\n");
+        res.push_str("\tret\n");
+        res.push_str(".Laccept:\n");
+        res.push_str("\tmovl\t$1, %eax\n");
+        res.push_str("\tret\n");
+        res.push_str("\t.size\tis_match, .-is_match\n");
+        res.push_str("\t.section\t.note.GNU-stack,\"\",@progbits\n");
+
+        Ok(res)
+    }
+}
+
+/// Compare chain testing the byte in `%eax`, bytes matching no run jumping
+/// to `fallthrough`.
+struct AsmSearch<'a> {
+    s: &'a mut String,
+    fallthrough: Option<NodeIndex>,
+    labels: &'a mut usize,
+}
+
+impl RunSearch for AsmSearch<'_> {
+    type Target = Option<NodeIndex>;
+    type Branch = String;
+
+    fn jump_if(&mut self, test: RunTest, target: Self::Target) -> Result<(), Error> {
+        let label = asm_label(target);
+
+        match test {
+            RunTest::Any => writeln!(self.s, "\tjmp\t{label}"),
+            RunTest::Equal(a) => {
+                writeln!(self.s, "\tcmpl\t${a}, %eax")?;
+                writeln!(self.s, "\tje\t{label}")
+            }
+            RunTest::Within(a, b) => {
+                writeln!(self.s, "\tleal\t-{a}(%rax), %edx")?;
+                writeln!(self.s, "\tcmpl\t${}, %edx", b - a)?;
+                writeln!(self.s, "\tjbe\t{label}")
+            }
+            RunTest::AtLeast(a) => {
+                writeln!(self.s, "\tcmpl\t${a}, %eax")?;
+                writeln!(self.s, "\tjae\t{label}")
+            }
+            RunTest::AtMost(b) => {
+                writeln!(self.s, "\tcmpl\t${b}, %eax")?;
+                writeln!(self.s, "\tjbe\t{label}")
+            }
+        }
+    }
+
+    fn split(&mut self, split: u8) -> Result<String, Error> {
+        let right_label = format!(".Lr{}", self.labels);
+        *self.labels += 1;
+
+        writeln!(self.s, "\tcmpl\t${split}, %eax")?;
+        writeln!(self.s, "\tjae\t{right_label}")?;
+
+        Ok(right_label)
+    }
+
+    fn split_else(&mut self, right_label: &String) -> Result<(), Error> {
+        writeln!(self.s, "\tjmp\t{}", asm_label(self.fallthrough))?;
+        writeln!(self.s, "{right_label}:")
+    }
+
+    fn split_end(&mut self, _: String) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests {
+    use std::fmt::Write;
+
+    use crate::{build, dfa::Dfa, testing};
+
+    #[test]
+    fn test_asm_against_dfa() {
+        if !testing::installed("as") || !testing::installed("cc") {
+            return;
+        }
+
+        let inputs = testing::random_inputs(200);
+        let mut harness = String::from("#include <stdio.h>\n");
+        let mut paths = Vec::new();
+        let mut expected = String::new();
+
+        harness.push_str(&testing::c_inputs(&inputs));
+
+        for (i, pattern) in testing::PATTERNS.iter().enumerate() {
+            let nfa = build(pattern).unwrap();
+            let dfa = Dfa::from_nfa(&nfa);
+            let code = nfa.compile_asm().unwrap();
+
+            let path = testing::scratch(&format!("asm-{i}.s"));
+            std::fs::write(&path, code.replace("is_match", &format!("is_match{i}"))).unwrap();
+            paths.push(path);
+
+            writeln!(harness, "int is_match{i}(const unsigned char *, size_t);").unwrap();
+            expected.push_str(&testing::verdicts(&dfa, &inputs));
+        }
+
+        let matchers: Vec<String> = (0..paths.len()).map(|i| format!("is_match{i}")).collect();
+        writeln!(
+            harness,
+            "\nint (*const matchers[])(const unsigned char *, size_t) = {{{}}};\n",
+            matchers.join(", ")
+        )
+        .unwrap();
+
+        harness.push_str("int main(void) {\n");
+        harness.push_str("\tsize_t i, j;\n\n");
+        harness.push_str("\tfor (i = 0; i < sizeof matchers / sizeof *matchers; i++)\n");
+        harness.push_str("\t\tfor (j = 0; j < sizeof inputs / sizeof *inputs; j++)\n");
+        harness.push_str("\t\t\tputchar('0' + matchers[i](inputs[j].bytes, inputs[j].len));\n\n");
+        harness.push_str("\treturn 0;\n}\n");
+
+        let path = testing::scratch("asm-harness.c");
+        std::fs::write(&path, harness).unwrap();
+        paths.push(path);
+
+        let sources: Vec<&std::path::Path> = paths.iter().map(|path| path.as_path()).collect();
+        let output = testing::run_c("asm", &sources);
+
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(output.unwrap(), expected);
+    }
+}
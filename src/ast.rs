@@ -0,0 +1,370 @@
+use std::fmt::{Display, Formatter, Write};
+
+use crate::nfa::{complement_ranges, merge_ranges, ALPHABET_MAX};
+
+/// Characters that have to be escaped to be matched literally.
+const METACHARACTERS: &[u8] = b"|&~!()[]^*.?+-{\\";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Ast {
+    /// Matches the empty string only.
+    Empty,
+    /// Matches one byte out of a set of ranges, nothing when there are none.
+    Class(Vec<(u8, u8)>),
+    Concat(Box<Ast>, Box<Ast>),
+    Union(Box<Ast>, Box<Ast>),
+    Intersection(Box<Ast>, Box<Ast>),
+    Complement(Box<Ast>),
+    Star(Box<Ast>),
+    Plus(Box<Ast>),
+    Optional(Box<Ast>),
+    Repeat(Box<Ast>, u64),
+}
+
+// Smart constructors, applying algebraic identities so that generated
+// expressions stay readable.
+impl Ast {
+    pub fn nothing() -> Ast {
+        Ast::Class(Vec::new())
+    }
+
+    pub fn is_nothing(&self) -> bool {
+        matches!(self, Ast::Class(ranges) if ranges.is_empty())
+    }
+
+    pub fn nullable(&self) -> bool {
+        match self {
+            Ast::Empty | Ast::Star(_) | Ast::Optional(_) => true,
+            Ast::Class(_) => false,
+            Ast::Concat(a, b) | Ast::Intersection(a, b) => a.nullable() && b.nullable(),
+            Ast::Union(a, b) => a.nullable() || b.nullable(),
+            Ast::Complement(a) => !a.nullable(),
+            Ast::Plus(a) => a.nullable(),
+            Ast::Repeat(a, n) => *n == 0 || a.nullable(),
+        }
+    }
+
+    /// Number of nodes, used to compare expressions.
+    pub fn size(&self) -> usize {
+        match self {
+            Ast::Empty | Ast::Class(_) => 1,
+            Ast::Concat(a, b) | Ast::Union(a, b) | Ast::Intersection(a, b) => {
+                1 + a.size() + b.size()
+            }
+            Ast::Complement(a)
+            | Ast::Star(a)
+            | Ast::Plus(a)
+            | Ast::Optional(a)
+            | Ast::Repeat(a, _) => 1 + a.size(),
+        }
+    }
+
+    /// The strings of an alternation of literals such as `foo|bar|baz`, or
+    /// `None` when the expression is anything else.
+    pub fn literal_alternatives(&self) -> Option<Vec<Vec<u8>>> {
+        match self {
+            Ast::Union(a, b) => {
+                let mut res = a.literal_alternatives()?;
+                res.extend(b.literal_alternatives()?);
+                Some(res)
+            }
+            ast => ast.literal().map(|literal| vec![literal]),
+        }
+    }
+
+    /// The only string matched by a concatenation of single bytes.
+    fn literal(&self) -> Option<Vec<u8>> {
+        match self {
+            Ast::Empty => Some(Vec::new()),
+            Ast::Class(ranges) => match ranges[..] {
+                [(a, b)] if a == b => Some(vec![a]),
+                _ => None,
+            },
+            Ast::Concat(a, b) => {
+                let mut res = a.literal()?;
+                res.extend(b.literal()?);
+                Some(res)
+            }
+            _ => None,
+        }
+    }
+
+    fn alternatives(self, res: &mut Vec<Ast>) {
+        match self {
+            Ast::Union(a, b) => {
+                a.alternatives(res);
+                b.alternatives(res);
+            }
+            Ast::Optional(a) => {
+                res.push(Ast::Empty);
+                a.alternatives(res);
+            }
+            ast => res.push(ast),
+        }
+    }
+
+    fn factors(self, res: &mut Vec<Ast>) {
+        match self {
+            Ast::Concat(a, b) => {
+                a.factors(res);
+                b.factors(res);
+            }
+            Ast::Empty => {}
+            ast => res.push(ast),
+        }
+    }
+
+    fn from_factors(factors: Vec<Ast>) -> Ast {
+        factors
+            .into_iter()
+            .reduce(|a, b| Ast::Concat(Box::new(a), Box::new(b)))
+            .unwrap_or(Ast::Empty)
+    }
+
+    pub fn union(a: Ast, b: Ast) -> Ast {
+        let mut alternatives = Vec::new();
+        a.alternatives(&mut alternatives);
+        b.alternatives(&mut alternatives);
+
+        let mut nullable = false;
+        let mut ranges = Vec::new();
+        let mut rest: Vec<Ast> = Vec::new();
+
+        for alternative in alternatives {
+            match alternative {
+                Ast::Empty => nullable = true,
+                Ast::Class(r) => ranges.extend(r),
+                ast if !rest.contains(&ast) => rest.push(ast),
+                _ => {}
+            }
+        }
+
+        if !ranges.is_empty() {
+            rest.insert(0, Ast::Class(merge_ranges(ranges)));
+        }
+
+        let rest = Ast::factor(rest);
+        let res = rest
+            .into_iter()
+            .reduce(|a, b| Ast::Union(Box::new(a), Box::new(b)));
+
+        match res {
+            Some(ast) if nullable => Ast::optional(ast),
+            Some(ast) => ast,
+            None if nullable => Ast::Empty,
+            None => Ast::nothing(),
+        }
+    }
+
+    /// Pulls common leading or trailing factors out of alternatives, turning
+    /// `ab|ac` into `a(b|c)` and `ac|bc` into `[ab]c`.
+    fn factor(mut alternatives: Vec<Ast>) -> Vec<Ast> {
+        for prefix in [true, false] {
+            let mut i = 0;
+
+            while i < alternatives.len() {
+                let mut j = i + 1;
+
+                while j < alternatives.len() {
+                    let mut a = Vec::new();
+                    let mut b = Vec::new();
+                    alternatives[i].clone().factors(&mut a);
+                    alternatives[j].clone().factors(&mut b);
+
+                    let shared = if prefix {
+                        !a.is_empty() && a.first() == b.first()
+                    } else {
+                        !a.is_empty() && a.last() == b.last()
+                    };
+
+                    if !shared {
+                        j += 1;
+                        continue;
+                    }
+
+                    let common = if prefix {
+                        b.remove(0);
+                        a.remove(0)
+                    } else {
+                        b.pop();
+                        a.pop().unwrap()
+                    };
+
+                    let tail = Ast::union(Ast::from_factors(a), Ast::from_factors(b));
+
+                    alternatives[i] = if prefix {
+                        Ast::concat(common, tail)
+                    } else {
+                        Ast::concat(tail, common)
+                    };
+                    alternatives.remove(j);
+                    j = i + 1;
+                }
+
+                i += 1;
+            }
+        }
+
+        alternatives
+    }
+
+    pub fn concat(a: Ast, b: Ast) -> Ast {
+        if a.is_nothing() || b.is_nothing() {
+            return Ast::nothing();
+        }
+
+        let mut factors = Vec::new();
+        a.factors(&mut factors);
+        b.factors(&mut factors);
+
+        let mut res: Vec<Ast> = Vec::new();
+
+        for factor in factors {
+            let merged = match (res.last(), &factor) {
+                // x x* and x* x are x+
+                (Some(x), Ast::Star(y)) | (Some(Ast::Star(y)), x) if x == y.as_ref() => {
+                    Some(Ast::Plus(y.clone()))
+                }
+                // x* x* and x+ x* are x* and x+
+                (Some(Ast::Star(x)), Ast::Star(y)) if x == y => Some(Ast::Star(x.clone())),
+                (Some(Ast::Plus(x)), Ast::Star(y)) | (Some(Ast::Star(y)), Ast::Plus(x))
+                    if x == y =>
+                {
+                    Some(Ast::Plus(x.clone()))
+                }
+                _ => None,
+            };
+
+            match merged {
+                Some(merged) => *res.last_mut().unwrap() = merged,
+                None => res.push(factor),
+            }
+        }
+
+        Ast::from_factors(res)
+    }
+
+    pub fn star(a: Ast) -> Ast {
+        match a {
+            Ast::Empty => Ast::Empty,
+            a if a.is_nothing() => Ast::Empty,
+            Ast::Star(a) | Ast::Plus(a) | Ast::Optional(a) => Ast::star(*a),
+            a => Ast::Star(Box::new(a)),
+        }
+    }
+
+    pub fn optional(a: Ast) -> Ast {
+        match a {
+            a if a.nullable() => a,
+            a if a.is_nothing() => Ast::Empty,
+            Ast::Plus(a) => Ast::Star(a),
+            a => Ast::Optional(Box::new(a)),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Ast::Union(_, _) => 0,
+            Ast::Intersection(_, _) => 1,
+            Ast::Concat(_, _) => 2,
+            Ast::Complement(_) => 3,
+            Ast::Star(_) | Ast::Plus(_) | Ast::Optional(_) | Ast::Repeat(_, _) => 4,
+            Ast::Empty | Ast::Class(_) => 5,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter<'_>, precedence: u8) -> std::fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+fn fmt_byte(f: &mut Formatter<'_>, c: u8) -> std::fmt::Result {
+    if METACHARACTERS.contains(&c) {
+        write!(f, "\\{}", c as char)
+    } else if c == b' ' || c.is_ascii_graphic() {
+        f.write_char(c as char)
+    } else {
+        write!(f, "\\x{c:02x}")
+    }
+}
+
+fn fmt_ranges(f: &mut Formatter<'_>, ranges: &[(u8, u8)]) -> std::fmt::Result {
+    for &(a, b) in ranges {
+        fmt_byte(f, a)?;
+
+        if b as u16 > a as u16 + 1 {
+            f.write_char('-')?;
+        }
+
+        if b > a {
+            fmt_byte(f, b)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Display for Ast {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ast::Empty => write!(f, "()"),
+            Ast::Class(ranges) => match ranges.as_slice() {
+                [(0, ALPHABET_MAX)] => write!(f, "."),
+                [(a, b)] if a == b => fmt_byte(f, *a),
+                ranges => {
+                    // Write whichever of the class and its negation is shorter
+                    let negated = complement_ranges(ranges);
+                    let within_alphabet = ranges.iter().all(|&(_, b)| b <= ALPHABET_MAX);
+
+                    if within_alphabet && !negated.is_empty() && negated.len() < ranges.len() {
+                        f.write_str("[^")?;
+                        fmt_ranges(f, &negated)?;
+                    } else {
+                        f.write_char('[')?;
+                        fmt_ranges(f, ranges)?;
+                    }
+
+                    f.write_char(']')
+                }
+            },
+            Ast::Concat(a, b) => {
+                a.fmt_operand(f, 2)?;
+                b.fmt_operand(f, 2)
+            }
+            Ast::Union(a, b) => {
+                a.fmt_operand(f, 0)?;
+                f.write_char('|')?;
+                b.fmt_operand(f, 0)
+            }
+            Ast::Intersection(a, b) => {
+                a.fmt_operand(f, 1)?;
+                f.write_char('&')?;
+                b.fmt_operand(f, 1)
+            }
+            Ast::Complement(a) => {
+                f.write_char('~')?;
+                a.fmt_operand(f, 3)
+            }
+            Ast::Star(a) => {
+                a.fmt_operand(f, 5)?;
+                f.write_char('*')
+            }
+            Ast::Plus(a) => {
+                a.fmt_operand(f, 5)?;
+                f.write_char('+')
+            }
+            Ast::Optional(a) => {
+                a.fmt_operand(f, 5)?;
+                f.write_char('?')
+            }
+            Ast::Repeat(a, n) => {
+                a.fmt_operand(f, 5)?;
+                write!(f, "{{{n}}}")
+            }
+        }
+    }
+}
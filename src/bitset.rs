@@ -1,13 +1,16 @@
-use std::marker::PhantomData;
+use std::{
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
 
-#[derive(Clone, Hash)]
+#[derive(Clone)]
 pub struct BitSet<T> {
     pub inner: Vec<u64>,
     pub universe_len: usize,
     mark: PhantomData<T>,
 }
 
-impl<'a, T: PartialEq> PartialEq for BitSet<T> {
+impl<T: PartialEq> PartialEq for BitSet<T> {
     fn eq(&self, other: &Self) -> bool {
         self.inner == other.inner
     }
@@ -15,7 +18,13 @@ impl<'a, T: PartialEq> PartialEq for BitSet<T> {
 
 impl<T: Eq> Eq for BitSet<T> {}
 
-impl<'a, T: PartialEq + Copy> BitSet<T> {
+impl<T> Hash for BitSet<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state);
+    }
+}
+
+impl<T: PartialEq + Copy> BitSet<T> {
     pub fn empty(universe_len: usize) -> BitSet<T> {
         BitSet {
             inner: vec![0; (universe_len / 64) + 1],
@@ -53,7 +62,7 @@ impl<'a, T: PartialEq + Copy> BitSet<T> {
     }
 
     pub fn contains(&self, index: usize) -> bool {
-        return self.inner[index / 64] & 1 << (index % 64) != 0;
+        self.inner[index / 64] & 1 << (index % 64) != 0
     }
 
     pub fn insert(&mut self, index: usize) -> bool {
@@ -133,7 +142,7 @@ impl<'a, T: PartialEq + Copy> BitSet<T> {
         }
     }
 
-    pub fn iter(&self) -> BitSetIterator<T> {
+    pub fn iter(&self) -> BitSetIterator<'_, T> {
         BitSetIterator {
             bitset: self,
             index: 0,
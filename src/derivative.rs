@@ -0,0 +1,268 @@
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::Ast,
+    dfa::Dfa,
+    nfa::{merge_ranges, ALPHABET_MAX},
+};
+
+// Constructors bringing derivatives to a canonical form: unions and
+// intersections are flattened, sorted and deduplicated, and concatenations
+// nested to the right. Expressions equal up to these identities compare
+// equal, which keeps the number of derivatives finite.
+
+fn operands(ast: Ast, intersection: bool, res: &mut Vec<Ast>) {
+    match ast {
+        Ast::Union(a, b) if !intersection => {
+            operands(*a, intersection, res);
+            operands(*b, intersection, res);
+        }
+        Ast::Intersection(a, b) if intersection => {
+            operands(*a, intersection, res);
+            operands(*b, intersection, res);
+        }
+        ast => res.push(ast),
+    }
+}
+
+fn from_operands(mut operands: Vec<Ast>, intersection: bool) -> Ast {
+    operands.sort();
+    operands.dedup();
+
+    operands
+        .into_iter()
+        .rev()
+        .reduce(|b, a| match intersection {
+            true => Ast::Intersection(Box::new(a), Box::new(b)),
+            false => Ast::Union(Box::new(a), Box::new(b)),
+        })
+        .unwrap_or_else(Ast::nothing)
+}
+
+fn or(a: Ast, b: Ast) -> Ast {
+    let mut alternatives = Vec::new();
+    operands(a, false, &mut alternatives);
+    operands(b, false, &mut alternatives);
+
+    let mut ranges = Vec::new();
+    let mut rest = Vec::new();
+
+    for alternative in alternatives {
+        match alternative {
+            Ast::Class(r) => ranges.extend(r),
+            ast => rest.push(ast),
+        }
+    }
+
+    if !ranges.is_empty() {
+        rest.push(Ast::Class(merge_ranges(ranges)));
+    }
+
+    from_operands(rest, false)
+}
+
+fn and(a: Ast, b: Ast) -> Ast {
+    if a.is_nothing() || b.is_nothing() {
+        return Ast::nothing();
+    }
+
+    let mut conjuncts = Vec::new();
+    operands(a, true, &mut conjuncts);
+    operands(b, true, &mut conjuncts);
+
+    from_operands(conjuncts, true)
+}
+
+fn then(a: Ast, b: Ast) -> Ast {
+    match (a, b) {
+        (a, b) if a.is_nothing() || b.is_nothing() => Ast::nothing(),
+        (Ast::Empty, b) => b,
+        (a, Ast::Empty) => a,
+        (Ast::Concat(x, y), b) => then(*x, then(*y, b)),
+        (a, b) => Ast::Concat(Box::new(a), Box::new(b)),
+    }
+}
+
+fn repeat(a: Ast, n: u64) -> Ast {
+    match n {
+        0 => Ast::Empty,
+        1 => a,
+        n => Ast::Repeat(Box::new(a), n),
+    }
+}
+
+impl Ast {
+    /// Expression matching the strings `w` such that `c w` is matched by
+    /// `self`, in canonical form.
+    pub fn derivative(&self, c: u8) -> Ast {
+        match self {
+            Ast::Empty => Ast::nothing(),
+            Ast::Class(ranges) if ranges.iter().any(|&(a, b)| a <= c && c <= b) => Ast::Empty,
+            Ast::Class(_) => Ast::nothing(),
+            Ast::Concat(a, b) => {
+                let res = then(a.derivative(c), (**b).clone());
+
+                match a.nullable() {
+                    true => or(res, b.derivative(c)),
+                    false => res,
+                }
+            }
+            Ast::Union(a, b) => or(a.derivative(c), b.derivative(c)),
+            Ast::Intersection(a, b) => and(a.derivative(c), b.derivative(c)),
+            // The complement only holds strings over the alphabet
+            Ast::Complement(a) if c <= ALPHABET_MAX => Ast::Complement(Box::new(a.derivative(c))),
+            Ast::Complement(_) => Ast::nothing(),
+            Ast::Star(a) | Ast::Plus(a) => then(a.derivative(c), Ast::Star(a.clone())),
+            Ast::Optional(a) => a.derivative(c),
+            Ast::Repeat(_, 0) => Ast::nothing(),
+            Ast::Repeat(a, n) => {
+                let rest = repeat((**a).clone(), n - 1);
+                let res = then(a.derivative(c), rest.clone());
+
+                match a.nullable() {
+                    true => or(res, rest.derivative(c)),
+                    false => res,
+                }
+            }
+        }
+    }
+
+    /// Adds the bytes where a class of the expression starts or ends.
+    fn boundaries(&self, res: &mut [bool; 257]) {
+        match self {
+            Ast::Empty => {}
+            Ast::Class(ranges) => {
+                for &(a, b) in ranges {
+                    res[a as usize] = true;
+                    res[b as usize + 1] = true;
+                }
+            }
+            Ast::Concat(a, b) | Ast::Union(a, b) | Ast::Intersection(a, b) => {
+                a.boundaries(res);
+                b.boundaries(res);
+            }
+            Ast::Complement(a)
+            | Ast::Star(a)
+            | Ast::Plus(a)
+            | Ast::Optional(a)
+            | Ast::Repeat(a, _) => a.boundaries(res),
+        }
+    }
+}
+
+impl Dfa {
+    /// Builds the DFA of `ast` from its Brzozowski derivatives, each state
+    /// standing for a derivative, without going through an NFA. Supports
+    /// `&` and `~` directly. Gives up with `None` past `max_states` states.
+    pub fn from_derivatives_within(ast: &Ast, max_states: usize) -> Option<Dfa> {
+        let mut boundaries = [false; 257];
+        boundaries[0] = true;
+        boundaries[ALPHABET_MAX as usize + 1] = true;
+        ast.boundaries(&mut boundaries);
+
+        // Bytes between two boundaries have the same derivatives
+        let mut class_map = [0; 256];
+        let mut class_bytes = Vec::new();
+
+        for c in 0..=255u8 {
+            if boundaries[c as usize] {
+                class_bytes.push(c);
+            }
+
+            class_map[c as usize] = (class_bytes.len() - 1) as u8;
+        }
+
+        if max_states == 0 {
+            return None;
+        }
+
+        let mut states = vec![ast.clone()];
+        let mut ids = FxHashMap::from_iter([(ast.clone(), 0)]);
+        let mut transitions = Vec::new();
+        let mut state = 0;
+
+        while state < states.len() {
+            for &c in class_bytes.iter() {
+                let derivative = states[state].derivative(c);
+
+                let next = match ids.get(&derivative) {
+                    Some(&next) => next,
+                    None if states.len() >= max_states => return None,
+                    None => {
+                        ids.insert(derivative.clone(), states.len());
+                        states.push(derivative);
+                        states.len() - 1
+                    }
+                };
+
+                transitions.push(next);
+            }
+
+            state += 1;
+        }
+
+        Some(Dfa {
+            class_map,
+            class_count: class_bytes.len(),
+            transitions,
+            accepting: states.iter().map(Ast::nullable).collect(),
+            start: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        determinize,
+        dfa::Dfa,
+        parse,
+        testing::{assert_equivalent, PATTERNS},
+    };
+
+    /// Random pattern over a few bytes, using every operator.
+    fn random_pattern(rng: &mut StdRng, depth: u32) -> String {
+        if depth == 0 {
+            let leaves = ["a", "b", "c", "[ab]", ".", "()", "[\\x7f-\\x80]"];
+            return leaves[rng.gen_range(0..leaves.len())].to_string();
+        }
+
+        let a = random_pattern(rng, depth - 1);
+
+        match rng.gen_range(0..9) {
+            0 => format!("({a}|{})", random_pattern(rng, depth - 1)),
+            1 => format!("({a}&{})", random_pattern(rng, depth - 1)),
+            2 => format!("{a}{}", random_pattern(rng, depth - 1)),
+            3 => format!("~({a})"),
+            4 => format!("({a})*"),
+            5 => format!("({a})+"),
+            6 => format!("({a})?"),
+            7 => format!("({a}){{{},{}}}", rng.gen_range(0..2), rng.gen_range(2..4)),
+            _ => format!("({a}){{{}}}", rng.gen_range(1..3)),
+        }
+    }
+
+    #[test]
+    fn test_against_subset_construction() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let random: Vec<String> = (0..500).map(|_| random_pattern(&mut rng, 4)).collect();
+
+        for pattern in PATTERNS
+            .iter()
+            .copied()
+            .chain(random.iter().map(String::as_str))
+        {
+            let ast = parse(pattern).unwrap();
+            let derivatives = Dfa::from_derivatives_within(&ast, usize::MAX).unwrap();
+
+            let (derivatives, dfa) = (derivatives.to_nfa(), determinize(&ast).minimize());
+            assert_equivalent(&derivatives, &dfa, pattern);
+
+            // Minimal DFAs of a language have the same size
+            let minimal = derivatives.minimize().graph.node_count();
+            assert_eq!(minimal, dfa.graph.node_count(), "{pattern}");
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use num_bigint::BigUint;
+use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
+
+use petgraph::graph::NodeIndex;
+
+use crate::nfa::{Nfa, State, Transition, ALPHABET_MAX};
+
+/// Complete transition table over byte classes. Unlike the graph form every
+/// state has a transition on every byte, going to an explicit dead state
+/// where the graph has none.
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    pub class_map: [u8; 256],
+    pub class_count: usize,
+    pub transitions: Vec<usize>,
+    pub accepting: Vec<bool>,
+    pub start: usize,
+}
+
+impl Dfa {
+    /// Builds the table of a deterministic automaton, such as the output of
+    /// `reduce_to_dfa` or `minimize`. Bytes that lead to the same state
+    /// from every state share a class.
+    pub fn from_nfa(nfa: &Nfa) -> Dfa {
+        let dead = nfa.graph.node_count();
+        let mut needs_dead = false;
+        let mut columns: Vec<Vec<usize>> = Vec::new();
+        let mut class_map = [0; 256];
+
+        for (a, b) in nfa.byte_classes() {
+            let column: Vec<usize> = nfa
+                .graph
+                .node_indices()
+                .map(|state| match nfa.step(state, a) {
+                    Some(target) => target.index(),
+                    None => {
+                        needs_dead = true;
+                        dead
+                    }
+                })
+                .collect();
+
+            let class = match columns.iter().position(|c| *c == column) {
+                Some(class) => class,
+                None => {
+                    columns.push(column);
+                    columns.len() - 1
+                }
+            };
+
+            for c in a..=b {
+                class_map[c as usize] = class as u8;
+            }
+        }
+
+        let mut dfa = Dfa {
+            class_map,
+            class_count: columns.len(),
+            transitions: Vec::new(),
+            accepting: Vec::new(),
+            start: nfa.start.index(),
+        };
+
+        for state in nfa.graph.node_indices() {
+            dfa.accepting.push(nfa.graph[state] == State::Accepting);
+
+            for column in columns.iter() {
+                dfa.transitions.push(column[state.index()]);
+            }
+        }
+
+        if needs_dead {
+            dfa.add_dead_state();
+        }
+
+        dfa
+    }
+
+    /// Accepts every string over the alphabet.
+    fn universe() -> Dfa {
+        let mut class_map = [1; 256];
+        class_map[..=ALPHABET_MAX as usize].fill(0);
+
+        Dfa {
+            class_map,
+            class_count: 2,
+            transitions: vec![0, 1, 1, 1],
+            accepting: vec![true, false],
+            start: 0,
+        }
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.accepting.len()
+    }
+
+    pub fn next(&self, state: usize, c: u8) -> usize {
+        self.transitions[state * self.class_count + self.class_map[c as usize] as usize]
+    }
+
+    pub fn is_match(&self, input: &[u8]) -> bool {
+        let state = input
+            .iter()
+            .fold(self.start, |state, &c| self.next(state, c));
+
+        self.accepting[state]
+    }
+
+    /// One of the shortest accepted strings, built from printable bytes
+    /// whenever a class contains some.
+    pub fn shortest_match(&self) -> Option<Vec<u8>> {
+        let mut examples = vec![None; self.class_count];
+
+        for c in (0..=255u8).rev() {
+            let example = &mut examples[self.class_map[c as usize] as usize];
+
+            if c.is_ascii_graphic() || example.is_none_or(|e: u8| !e.is_ascii_graphic()) {
+                *example = Some(c);
+            }
+        }
+
+        let mut parents: Vec<Option<(usize, u8)>> = vec![None; self.state_count()];
+        let mut visited = vec![false; self.state_count()];
+        let mut work_list = VecDeque::from([self.start]);
+        visited[self.start] = true;
+
+        while let Some(state) = work_list.pop_front() {
+            if self.accepting[state] {
+                let mut res = Vec::new();
+                let mut state = state;
+
+                while let Some((parent, c)) = parents[state] {
+                    res.push(c);
+                    state = parent;
+                }
+
+                res.reverse();
+                return Some(res);
+            }
+
+            for (class, example) in examples.iter().enumerate() {
+                let next = self.transitions[state * self.class_count + class];
+
+                if !visited[next] {
+                    visited[next] = true;
+                    parents[next] = Some((state, example.unwrap()));
+                    work_list.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn add_dead_state(&mut self) -> usize {
+        let dead = self.state_count();
+        self.accepting.push(false);
+        self.transitions
+            .extend(std::iter::repeat_n(dead, self.class_count));
+
+        dead
+    }
+
+    /// Accepts every string over the alphabet that `self` rejects.
+    pub fn complement(&self) -> Dfa {
+        self.complement_within(usize::MAX).unwrap()
+    }
+
+    pub fn complement_within(&self, max_states: usize) -> Option<Dfa> {
+        self.product_within(&Dfa::universe(), |a, b| !a && b, max_states)
+    }
+
+    /// Runs both automata side by side, accepting when `accept` holds for
+    /// their respective verdicts.
+    pub fn product(&self, other: &Dfa, accept: impl Fn(bool, bool) -> bool) -> Dfa {
+        self.product_within(other, accept, usize::MAX).unwrap()
+    }
+
+    /// Like `product`, giving up with `None` once the product would need
+    /// more than `max_states` states.
+    pub fn product_within(
+        &self,
+        other: &Dfa,
+        accept: impl Fn(bool, bool) -> bool,
+        max_states: usize,
+    ) -> Option<Dfa> {
+        let mut class_pairs = Vec::new();
+        let mut class_map = [0; 256];
+
+        for (c, class) in class_map.iter_mut().enumerate() {
+            let pair = (self.class_map[c], other.class_map[c]);
+
+            *class = match class_pairs.iter().position(|p| *p == pair) {
+                Some(class) => class as u8,
+                None => {
+                    class_pairs.push(pair);
+                    (class_pairs.len() - 1) as u8
+                }
+            };
+        }
+
+        let mut dfa = Dfa {
+            class_map,
+            class_count: class_pairs.len(),
+            transitions: Vec::new(),
+            accepting: Vec::new(),
+            start: 0,
+        };
+
+        if max_states == 0 {
+            return None;
+        }
+
+        let mut states = FxHashMap::default();
+        let mut work_list = VecDeque::from([(self.start, other.start)]);
+        states.insert((self.start, other.start), 0);
+
+        while let Some((p, q)) = work_list.pop_front() {
+            dfa.accepting
+                .push(accept(self.accepting[p], other.accepting[q]));
+
+            for &(a, b) in class_pairs.iter() {
+                let target = (
+                    self.transitions[p * self.class_count + a as usize],
+                    other.transitions[q * other.class_count + b as usize],
+                );
+
+                let id = match states.get(&target) {
+                    Some(&id) => id,
+                    None if states.len() >= max_states => return None,
+                    None => {
+                        let id = states.len();
+                        states.insert(target, id);
+                        work_list.push_back(target);
+                        id
+                    }
+                };
+
+                dfa.transitions.push(id);
+            }
+        }
+
+        Some(dfa)
+    }
+
+    pub fn intersection(&self, other: &Dfa) -> Dfa {
+        self.intersection_within(other, usize::MAX).unwrap()
+    }
+
+    pub fn intersection_within(&self, other: &Dfa, max_states: usize) -> Option<Dfa> {
+        self.product_within(other, |a, b| a && b, max_states)
+    }
+
+    /// States that can reach an accepting state using only bytes of `alphabet`.
+    fn productive_states(&self, alphabet: &[u8]) -> Vec<bool> {
+        let mut classes: Vec<usize> = alphabet
+            .iter()
+            .map(|&c| self.class_map[c as usize] as usize)
+            .collect();
+        classes.sort();
+        classes.dedup();
+
+        let mut predecessors = vec![Vec::new(); self.state_count()];
+
+        for state in 0..self.state_count() {
+            for &class in classes.iter() {
+                predecessors[self.transitions[state * self.class_count + class]].push(state);
+            }
+        }
+
+        // Walk back from the accepting states
+        let mut productive = self.accepting.clone();
+        let mut stack: Vec<usize> = (0..self.state_count()).filter(|&s| productive[s]).collect();
+
+        while let Some(state) = stack.pop() {
+            for &prev in predecessors[state].iter() {
+                if !productive[prev] {
+                    productive[prev] = true;
+                    stack.push(prev);
+                }
+            }
+        }
+
+        productive
+    }
+
+    /// States reachable from the start that can still reach an accepting state.
+    pub fn live_states(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.state_count()];
+        let mut stack = vec![self.start];
+        reachable[self.start] = true;
+
+        while let Some(state) = stack.pop() {
+            for class in 0..self.class_count {
+                let next = self.transitions[state * self.class_count + class];
+
+                if !reachable[next] {
+                    reachable[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        let all: Vec<u8> = (0..=255).collect();
+
+        reachable
+            .into_iter()
+            .zip(self.productive_states(&all))
+            .map(|(r, p)| r && p)
+            .collect()
+    }
+
+    /// Accepted strings over `alphabet` in shortlex order: by length, then
+    /// byte by byte.
+    pub fn shortlex(&self, alphabet: &[u8]) -> Shortlex<'_> {
+        let mut alphabet = alphabet.to_vec();
+        alphabet.sort();
+        alphabet.dedup();
+
+        let productive = self.productive_states(&alphabet);
+        let mut queue = VecDeque::new();
+
+        if productive[self.start] {
+            queue.push_back((self.start, Vec::new()));
+        }
+
+        Shortlex {
+            dfa: self,
+            alphabet,
+            productive,
+            queue,
+        }
+    }
+
+    /// Number of accepted strings over `alphabet` of every length up to `max_len`.
+    pub fn count_by_length(&self, alphabet: &[u8], max_len: usize) -> Vec<BigUint> {
+        let mut alphabet = alphabet.to_vec();
+        alphabet.sort();
+        alphabet.dedup();
+
+        let mut counts = vec![BigUint::ZERO; self.state_count()];
+        let mut res = Vec::new();
+        counts[self.start] = BigUint::from(1u8);
+
+        for len in 0..=max_len {
+            res.push(
+                counts
+                    .iter()
+                    .zip(self.accepting.iter())
+                    .filter(|(_, &accepting)| accepting)
+                    .map(|(count, _)| count)
+                    .sum(),
+            );
+
+            if len == max_len {
+                break;
+            }
+
+            let mut next = vec![BigUint::ZERO; self.state_count()];
+
+            for (state, count) in counts.iter().enumerate() {
+                for &c in alphabet.iter() {
+                    next[self.next(state, c)] += count;
+                }
+            }
+
+            counts = next;
+        }
+
+        res
+    }
+
+    /// Converts back to the graph form, dropping dead and unreachable states.
+    pub fn to_nfa(&self) -> Nfa {
+        let live = self.live_states();
+        let mut nfa = Nfa::new();
+        let mut mapping = FxHashMap::default();
+
+        let start = nfa.add_state();
+        nfa.set_start(start);
+        mapping.insert(self.start, start);
+
+        if !live[self.start] {
+            return nfa;
+        }
+
+        let mut work_list = VecDeque::from([self.start]);
+
+        while let Some(state) = work_list.pop_front() {
+            let node = *mapping.get(&state).unwrap();
+
+            if self.accepting[state] {
+                nfa.make_accepting(node);
+            }
+
+            let mut edges: Vec<(usize, Vec<(u8, u8)>)> = Vec::new();
+
+            for c in 0..=255u8 {
+                let target = self.next(state, c);
+
+                if !live[target] {
+                    continue;
+                }
+
+                match edges.iter_mut().find(|(t, _)| *t == target) {
+                    Some((_, ranges)) => ranges.push((c, c)),
+                    None => edges.push((target, vec![(c, c)])),
+                }
+            }
+
+            for (target, ranges) in edges {
+                let target_node: NodeIndex = *mapping.entry(target).or_insert_with(|| {
+                    work_list.push_back(target);
+                    nfa.add_state()
+                });
+
+                nfa.add_transition(node, target_node, Transition::from_ranges(ranges));
+            }
+        }
+
+        nfa
+    }
+}
+
+pub struct Shortlex<'a> {
+    dfa: &'a Dfa,
+    alphabet: Vec<u8>,
+    productive: Vec<bool>,
+    queue: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl Iterator for Shortlex<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Only productive states are queued, so every prefix leads to at
+        // least one accepted string and the search never stalls.
+        while let Some((state, prefix)) = self.queue.pop_front() {
+            for &c in self.alphabet.iter() {
+                let next = self.dfa.next(state, c);
+
+                if self.productive[next] {
+                    let mut string = prefix.clone();
+                    string.push(c);
+                    self.queue.push_back((next, string));
+                }
+            }
+
+            if self.dfa.accepting[state] {
+                return Some(prefix);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+
+    use crate::{build, dfa::Dfa};
+
+    #[test]
+    fn test_count_by_length() {
+        let dfa = Dfa::from_nfa(&build("(ab)*").unwrap());
+        let counts = |alphabet: &[u8]| -> Vec<u32> {
+            let counts = dfa.count_by_length(alphabet, 4);
+            counts
+                .iter()
+                .map(|count| u32::try_from(count).unwrap())
+                .collect()
+        };
+
+        assert_eq!(counts(b"ab"), [1, 0, 1, 0, 1]);
+        assert_eq!(counts(b"abab"), [1, 0, 1, 0, 1]);
+        assert_eq!(counts(b"a"), [1, 0, 0, 0, 0]);
+
+        let dfa = Dfa::from_nfa(&build("[a-c]*").unwrap());
+        let counts = dfa.count_by_length(b"abcabc", 3);
+        assert_eq!(counts, [1u8, 3, 9, 27].map(BigUint::from));
+
+        let shortlex: Vec<Vec<u8>> = dfa.shortlex(b"cba").take(4).collect();
+        assert_eq!(shortlex, [&b""[..], b"a", b"b", b"c"]);
+    }
+
+    #[test]
+    fn test_product_within() {
+        let a = Dfa::from_nfa(&build("(.{7})*").unwrap());
+        let b = Dfa::from_nfa(&build("(.{5})*").unwrap());
+        let count = a.intersection(&b).state_count();
+
+        assert!(count > 35);
+        assert!(a.intersection_within(&b, count).is_some());
+        assert!(a.intersection_within(&b, count - 1).is_none());
+        assert!(a.complement_within(0).is_none());
+    }
+}
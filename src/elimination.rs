@@ -0,0 +1,136 @@
+use crate::{ast::Ast, dfa::Dfa, nfa::merge_ranges};
+
+impl Dfa {
+    /// Converts the automaton back to an expression by state elimination.
+    /// States are removed cheapest first, the cost of a state being the size
+    /// of the expressions its removal would copy.
+    pub fn to_regex(&self) -> Ast {
+        let live = self.live_states();
+        let states: Vec<usize> = (0..self.state_count()).filter(|&s| live[s]).collect();
+
+        // Generalized automaton over the live states plus a fresh start and
+        // end, edges labelled by expressions and `None` standing for no edge
+        let n = states.len() + 2;
+        let (start, end) = (n - 2, n - 1);
+        let mut edges: Vec<Vec<Option<Ast>>> = vec![vec![None; n]; n];
+
+        for (i, &p) in states.iter().enumerate() {
+            for (j, &q) in states.iter().enumerate() {
+                let ranges: Vec<(u8, u8)> = (0..=255u8)
+                    .filter(|&c| self.next(p, c) == q)
+                    .map(|c| (c, c))
+                    .collect();
+
+                if !ranges.is_empty() {
+                    edges[i][j] = Some(Ast::Class(merge_ranges(ranges)));
+                }
+            }
+
+            if p == self.start {
+                edges[start][i] = Some(Ast::Empty);
+            }
+
+            if self.accepting[p] {
+                edges[i][end] = Some(Ast::Empty);
+            }
+        }
+
+        let mut remaining: Vec<usize> = (0..states.len()).collect();
+
+        while !remaining.is_empty() {
+            let cost = |k: usize| {
+                let ins: Vec<&Ast> = (0..n)
+                    .filter(|&p| p != k)
+                    .filter_map(|p| edges[p][k].as_ref())
+                    .collect();
+                let outs: Vec<&Ast> = (0..n)
+                    .filter(|&q| q != k)
+                    .filter_map(|q| edges[k][q].as_ref())
+                    .collect();
+                let looping = edges[k][k].as_ref().map_or(0, Ast::size);
+
+                ins.iter().map(|a| a.size()).sum::<usize>() * outs.len()
+                    + outs.iter().map(|a| a.size()).sum::<usize>() * ins.len()
+                    + looping * ins.len() * outs.len()
+            };
+
+            let index = (0..remaining.len())
+                .min_by_key(|&i| cost(remaining[i]))
+                .unwrap();
+            let k = remaining.swap_remove(index);
+
+            let looping = edges[k][k].take().map(Ast::star);
+            let outs: Vec<(usize, Ast)> = edges[k]
+                .iter()
+                .enumerate()
+                .filter_map(|(q, out)| Some((q, out.clone()?)))
+                .collect();
+
+            for row in edges.iter_mut() {
+                let Some(into) = row[k].take() else {
+                    continue;
+                };
+
+                let into = match &looping {
+                    Some(looping) => Ast::concat(into, looping.clone()),
+                    None => into,
+                };
+
+                for (q, out) in outs.iter() {
+                    let path = Ast::concat(into.clone(), out.clone());
+
+                    row[*q] = Some(match row[*q].take() {
+                        Some(existing) => Ast::union(existing, path),
+                        None => path,
+                    });
+                }
+            }
+
+            edges[k].fill(None);
+        }
+
+        edges[start][end].take().unwrap_or_else(Ast::nothing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        build, determinize,
+        dfa::Dfa,
+        testing::{assert_equivalent, PATTERNS},
+    };
+
+    // Classes ending at the last byte
+    const HIGH_BYTES: &[&str] = &[
+        "\\xff",
+        "[\\x10\\xff]",
+        "[\\xfe\\xff]+",
+        "[\\x7f-\\xff]*a",
+        "[\\x00-\\xff]x",
+    ];
+
+    #[test]
+    fn test_round_trip() {
+        for pattern in PATTERNS.iter().chain(HIGH_BYTES) {
+            let dfa = build(pattern).unwrap();
+            let regex = Dfa::from_nfa(&dfa).to_regex();
+
+            let from_ast = determinize(&regex);
+            assert_equivalent(&dfa, &from_ast, format_args!("{pattern} -> {regex}"));
+
+            let reparsed = build(&regex.to_string()).unwrap();
+            assert_equivalent(&dfa, &reparsed, format_args!("{pattern} -> {regex}"));
+        }
+    }
+
+    #[test]
+    fn test_idempotent() {
+        for pattern in PATTERNS.iter().chain(HIGH_BYTES) {
+            let regex = Dfa::from_nfa(&build(pattern).unwrap()).to_regex();
+            let again = Dfa::from_nfa(&build(&regex.to_string()).unwrap()).to_regex();
+
+            assert_eq!(regex, again, "{pattern}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Literal syntax of the emitted languages. Printable characters are kept
+//! as they are for readability, anything else is written numerically.
+
+/// A byte as a C character constant, or as a hexadecimal integer when it is
+/// not printable ASCII.
+pub fn c_char(c: u8) -> String {
+    match c {
+        b'\'' | b'\\' => format!("'\\{}'", c as char),
+        b' ' => "' '".to_string(),
+        c if c.is_ascii_graphic() => format!("'{}'", c as char),
+        c => format!("0x{c:02x}"),
+    }
+}
+
+/// A byte as a Rust byte literal.
+pub fn rust_byte(c: u8) -> String {
+    let escaped: String = std::ascii::escape_default(c).map(char::from).collect();
+
+    format!("b'{escaped}'")
+}
+
+/// A byte as it should appear in a label, escaping quotes, backslashes and
+/// anything that is not printable ASCII.
+pub fn label_byte(c: u8) -> String {
+    std::ascii::escape_default(c).map(char::from).collect()
+}
+
+/// Contents of a DOT quoted string.
+pub fn dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use crate::{build, testing};
+
+    /// Pattern matching the single byte `c`.
+    fn pattern(c: u8) -> String {
+        format!("\\x{c:02x}")
+    }
+
+    #[test]
+    fn test_c_single_bytes() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        // Every matcher gets its own names so they fit in one program
+        let mut source = String::from("#include <stdio.h>\n#include \"stack.h\"\n\n");
+        let mut main = String::from("int main(void) {\n\tchar input[2] = {0, 0};\n\n");
+
+        for c in 0..=255u8 {
+            let code = build(&pattern(c)).unwrap().compile().unwrap();
+            let code = code.strip_prefix("#include \"stack.h\"\n").unwrap();
+
+            writeln!(source, "#define matches matches_{c}").unwrap();
+            writeln!(source, "#define accepting accepting_{c}").unwrap();
+            writeln!(source, "{code}#undef matches\n#undef accepting\n").unwrap();
+
+            // The matchers read NUL-terminated strings
+            if c != 0 {
+                writeln!(main, "\tinput[0] = (char){c};").unwrap();
+                writeln!(main, "\tprintf(\"%d\", matches_{c}(input));").unwrap();
+                writeln!(main, "\tinput[0] = (char){};", c ^ 1).unwrap();
+                writeln!(main, "\tprintf(\"%d\", matches_{c}(input));").unwrap();
+            }
+        }
+
+        main.push_str("\treturn 0;\n}\n");
+        source.push_str(&main);
+
+        let path = testing::scratch("single-bytes.c");
+        std::fs::write(&path, source).unwrap();
+        let output = testing::run_c("single-bytes", &[&path]);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(output.unwrap(), "10".repeat(255));
+    }
+
+    /// Unescapes the contents of a DOT quoted string, failing on unescaped
+    /// quotes and on anything that is not printable ASCII.
+    fn dot_unescape(s: &str) -> Option<String> {
+        let mut res = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return None,
+                '\\' => res.push(chars.next()?),
+                'ε' => res.push(c),
+                c if c.is_ascii_graphic() || c == ' ' => res.push(c),
+                _ => return None,
+            }
+        }
+
+        Some(res)
+    }
+
+    #[test]
+    fn test_dot_single_bytes() {
+        for c in 0..=255u8 {
+            let nfa = build(&pattern(c)).unwrap();
+            let dot = nfa.to_dot().unwrap();
+            let labels: Vec<&str> = dot
+                .lines()
+                .filter_map(|line| line.split_once("[label = \"")?.1.strip_suffix("\"];"))
+                .collect();
+
+            let expected: Vec<String> = nfa
+                .graph
+                .edge_weights()
+                .map(|transition| transition.to_string())
+                .collect();
+
+            let labels: Vec<Option<String>> = labels.into_iter().map(dot_unescape).collect();
+            let expected: Vec<Option<String>> = expected.into_iter().map(Some).collect();
+
+            assert_eq!(labels, expected, "{c:#04x}");
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    ast::Ast,
+    nfa::{Nfa, Transition},
+};
+
+/// Position automaton of an expression, with one position per byte class
+/// occurring in it. Reading a byte moves from a position to the following
+/// positions whose class contains the byte, without epsilon transitions.
+pub struct Glushkov {
+    /// Bytes matched at each position
+    pub classes: Vec<Vec<(u8, u8)>>,
+    /// Positions a match can start with
+    pub first: Vec<usize>,
+    /// Positions a match can end with
+    pub last: Vec<usize>,
+    /// Positions that can come right after each position
+    pub follow: Vec<Vec<usize>>,
+    /// Whether the empty string matches
+    pub nullable: bool,
+}
+
+impl Glushkov {
+    /// Linearizes `ast`, returning `None` when it uses intersection or
+    /// complement, which have no position automaton.
+    pub fn new(ast: &Ast) -> Option<Glushkov> {
+        Glushkov::new_within(ast, usize::MAX)
+    }
+
+    /// Like `new`, but gives up with `None` once the expression needs more
+    /// than `max_positions` positions, before expanding repeats any further.
+    pub fn new_within(ast: &Ast, max_positions: usize) -> Option<Glushkov> {
+        let mut glushkov = Glushkov {
+            classes: Vec::new(),
+            first: Vec::new(),
+            last: Vec::new(),
+            follow: Vec::new(),
+            nullable: false,
+        };
+
+        let (first, last, nullable) = glushkov.positions(ast, max_positions)?;
+
+        for follow in glushkov.follow.iter_mut() {
+            follow.sort();
+            follow.dedup();
+        }
+
+        glushkov.first = first;
+        glushkov.last = last;
+        glushkov.nullable = nullable;
+
+        Some(glushkov)
+    }
+
+    pub fn position_count(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// First positions, last positions and nullability of `ast`, adding its
+    /// positions and their follow sets, or `None` past `max` positions.
+    fn positions(&mut self, ast: &Ast, max: usize) -> Option<(Vec<usize>, Vec<usize>, bool)> {
+        let res = match ast {
+            Ast::Empty => (Vec::new(), Vec::new(), true),
+            Ast::Class(_) if self.classes.len() >= max => return None,
+            Ast::Class(ranges) => {
+                let position = self.classes.len();
+                self.classes.push(ranges.clone());
+                self.follow.push(Vec::new());
+
+                (vec![position], vec![position], false)
+            }
+            Ast::Concat(a, b) => {
+                let (first_a, last_a, nullable_a) = self.positions(a, max)?;
+                let (first_b, last_b, nullable_b) = self.positions(b, max)?;
+
+                self.link(&last_a, &first_b);
+
+                let mut first = first_a;
+                let mut last = last_b;
+
+                if nullable_a {
+                    first.extend(first_b);
+                }
+
+                if nullable_b {
+                    last.extend(last_a);
+                }
+
+                (first, last, nullable_a && nullable_b)
+            }
+            Ast::Union(a, b) => {
+                let (mut first, mut last, nullable_a) = self.positions(a, max)?;
+                let (first_b, last_b, nullable_b) = self.positions(b, max)?;
+
+                first.extend(first_b);
+                last.extend(last_b);
+
+                (first, last, nullable_a || nullable_b)
+            }
+            Ast::Star(a) | Ast::Plus(a) => {
+                let (first, last, nullable) = self.positions(a, max)?;
+
+                self.link(&last, &first);
+
+                (first, last, nullable || matches!(ast, Ast::Star(_)))
+            }
+            Ast::Optional(a) => {
+                let (first, last, _) = self.positions(a, max)?;
+
+                (first, last, true)
+            }
+            Ast::Repeat(a, n) => {
+                let mut res = (Vec::new(), Vec::new(), true);
+
+                // Every copy gets positions of its own
+                for _ in 0..*n {
+                    let count = self.classes.len();
+                    let (first, last, nullable) = self.positions(a, max)?;
+                    let (res_first, res_last, res_nullable) = &mut res;
+
+                    self.link(res_last, &first);
+
+                    if *res_nullable {
+                        res_first.extend(first);
+                    }
+
+                    if nullable {
+                        res_last.extend(last);
+                    } else {
+                        *res_last = last;
+                    }
+
+                    *res_nullable = *res_nullable && nullable;
+
+                    // Copies without positions all match only the empty string
+                    if self.classes.len() == count {
+                        break;
+                    }
+                }
+
+                res
+            }
+            Ast::Intersection(..) | Ast::Complement(_) => return None,
+        };
+
+        Some(res)
+    }
+
+    /// Lets every position of `to` follow every position of `from`.
+    fn link(&mut self, from: &[usize], to: &[usize]) {
+        for &position in from {
+            self.follow[position].extend(to);
+        }
+    }
+}
+
+impl Nfa {
+    /// Builds the position automaton as an NFA without epsilon transitions:
+    /// a start state, then one state per position, entered on the bytes of
+    /// the position.
+    pub fn from_glushkov(glushkov: &Glushkov) -> Nfa {
+        let mut nfa = Nfa::new();
+        let start = nfa.add_state();
+        nfa.set_start(start);
+
+        let positions: Vec<NodeIndex> = glushkov.classes.iter().map(|_| nfa.add_state()).collect();
+        let link = |nfa: &mut Nfa, from: NodeIndex, to: usize| {
+            let ranges = &glushkov.classes[to];
+
+            if !ranges.is_empty() {
+                nfa.add_transition(from, positions[to], Transition::from_ranges(ranges.clone()));
+            }
+        };
+
+        for &position in glushkov.first.iter() {
+            link(&mut nfa, start, position);
+        }
+
+        for (from, follow) in glushkov.follow.iter().enumerate() {
+            for &to in follow {
+                link(&mut nfa, positions[from], to);
+            }
+        }
+
+        for &position in glushkov.last.iter() {
+            nfa.make_accepting(positions[position]);
+        }
+
+        if glushkov.nullable {
+            nfa.make_accepting(start);
+        }
+
+        nfa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glushkov;
+    use crate::{
+        determinize,
+        dfa::Dfa,
+        nfa::Nfa,
+        parse,
+        testing::{assert_equivalent, PATTERNS},
+    };
+
+    #[test]
+    fn test_constructions_agree() {
+        let corpus = [
+            "a{2,4}",
+            "[ab]{,3}c{1,}",
+            "(a|b){2,}&~(.*aa.*)",
+            "~([a-c]{1,2})&[a-c]*",
+            "(x{0,2}|y{2})*&(.*x.*)",
+        ];
+
+        for pattern in PATTERNS.iter().chain(&corpus) {
+            let ast = parse(pattern).unwrap();
+            let thompson = determinize(&ast);
+
+            let derivatives = Dfa::from_derivatives_within(&ast, usize::MAX).unwrap();
+            assert_equivalent(&thompson, &derivatives.to_nfa(), pattern);
+
+            if let Some(glushkov) = Glushkov::new(&ast) {
+                let glushkov = Nfa::from_glushkov(&glushkov).simplify().reduce_to_dfa();
+                assert_equivalent(&thompson, &glushkov, pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_positions() {
+        let positions = |pattern| {
+            Glushkov::new_within(&parse(pattern).unwrap(), 63).map(|g| g.position_count())
+        };
+
+        assert_eq!(positions("a{63}"), Some(63));
+        assert_eq!(positions("a{64}"), None);
+        assert_eq!(positions("a{1000000000}"), None);
+        assert_eq!(positions("(){1000000000}b"), Some(1));
+    }
+}
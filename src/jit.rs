@@ -0,0 +1,356 @@
+use std::fmt::Error;
+
+use petgraph::{graph::NodeIndex, visit::IntoNodeReferences};
+
+use crate::{
+    dfa::Dfa,
+    nfa::{densest_target, search_runs, RunSearch, RunTest, State},
+};
+
+/// Matcher returned by `Dfa::jit`.
+pub enum Matcher {
+    /// Machine code generated for the automaton
+    Native(Code),
+    /// The transition table, when no code could be generated
+    Table(Box<Dfa>),
+}
+
+impl Matcher {
+    /// Tells whether the whole input is matched.
+    pub fn is_match(&self, input: &[u8]) -> bool {
+        match self {
+            Matcher::Native(code) => code.call(input),
+            Matcher::Table(dfa) => dfa.is_match(input),
+        }
+    }
+}
+
+/// Executable page holding `int is_match(const unsigned char *, size_t)`.
+pub struct Code {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The page is never written to once it is executable
+unsafe impl Send for Code {}
+unsafe impl Sync for Code {}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const PROT_WRITE: c_int = 2;
+    pub const PROT_EXEC: c_int = 4;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MAP_ANONYMOUS: c_int = 0x20;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl Code {
+    /// Copies `bytes` to a fresh page, which is then made executable.
+    fn new(bytes: &[u8]) -> Option<Code> {
+        let len = bytes.len().max(1);
+
+        // SAFETY: a private anonymous mapping aliases no memory of ours, and
+        // `bytes` fits in it
+        unsafe {
+            let ptr = sys::mmap(
+                std::ptr::null_mut(),
+                len,
+                sys::PROT_READ | sys::PROT_WRITE,
+                sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+
+            if ptr as isize == -1 {
+                return None;
+            }
+
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr as *mut u8, bytes.len());
+
+            if sys::mprotect(ptr, len, sys::PROT_READ | sys::PROT_EXEC) != 0 {
+                sys::munmap(ptr, len);
+                return None;
+            }
+
+            Some(Code {
+                ptr: ptr as *mut u8,
+                len,
+            })
+        }
+    }
+
+    fn call(&self, input: &[u8]) -> bool {
+        // SAFETY: the page holds a function with this signature that only
+        // reads the `len` bytes at `input`
+        unsafe {
+            let f: extern "C" fn(*const u8, usize) -> u32 = std::mem::transmute(self.ptr);
+            f(input.as_ptr(), input.len()) != 0
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl Drop for Code {
+    fn drop(&mut self) {
+        // SAFETY: the page was mapped by `Code::new` and is no longer used
+        unsafe {
+            sys::munmap(self.ptr as *mut std::ffi::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+impl Code {
+    fn new(_: &[u8]) -> Option<Code> {
+        None
+    }
+
+    fn call(&self, _: &[u8]) -> bool {
+        unreachable!("no native code on this platform")
+    }
+}
+
+/// Machine code being assembled, with jumps to labels patched at the end.
+struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    jumps: Vec<(usize, usize)>,
+}
+
+impl Assembler {
+    fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn bind(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// Jump instruction `opcode` with a 32-bit displacement to `label`.
+    fn jump(&mut self, opcode: &[u8], label: usize) {
+        self.emit(opcode);
+        self.jumps.push((self.code.len(), label));
+        self.emit(&[0; 4]);
+    }
+
+    fn jmp(&mut self, label: usize) {
+        self.jump(&[0xe9], label);
+    }
+
+    /// `cmpl $imm, %eax`
+    fn cmp_eax(&mut self, imm: u8) {
+        self.emit(&[0x3d]);
+        self.emit(&(imm as u32).to_le_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for &(at, label) in self.jumps.iter() {
+            let target = self.labels[label].expect("unbound label");
+            let offset = target as i32 - (at + 4) as i32;
+            self.code[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+
+        self.code
+    }
+}
+
+const JE: [u8; 2] = [0x0f, 0x84];
+const JAE: [u8; 2] = [0x0f, 0x83];
+const JBE: [u8; 2] = [0x0f, 0x86];
+
+impl Dfa {
+    /// Compiles the automaton to x86-64 machine code in an executable page,
+    /// falling back to the table when that is not possible.
+    pub fn jit(&self) -> Matcher {
+        match Code::new(&self.machine_code()) {
+            Some(code) => Matcher::Native(code),
+            None => Matcher::Table(Box::new(self.clone())),
+        }
+    }
+
+    /// Machine code of the function `Nfa::compile_asm` would emit, with the
+    /// same dispatch on byte runs.
+    fn machine_code(&self) -> Vec<u8> {
+        let nfa = self.to_nfa();
+        let live = nfa.live_states();
+        let mut asm = Assembler {
+            code: Vec::new(),
+            labels: Vec::new(),
+            jumps: Vec::new(),
+        };
+
+        let states: Vec<usize> = nfa.graph.node_indices().map(|_| asm.label()).collect();
+        let reject = asm.label();
+        let accept = asm.label();
+        let label = |target: Option<NodeIndex>| match target {
+            Some(state) => states[state.index()],
+            None => reject,
+        };
+
+        // addq %rdi, %rsi
+        asm.emit(&[0x48, 0x01, 0xfe]);
+        asm.jmp(states[nfa.start.index()]);
+
+        for (index, state) in nfa.graph.node_references() {
+            asm.bind(states[index.index()]);
+
+            let done = match state {
+                State::Accepting => accept,
+                State::NotAccepting => reject,
+            };
+
+            // cmpq %rsi, %rdi; je done; movzbl (%rdi), %eax; incq %rdi
+            asm.emit(&[0x48, 0x39, 0xf7]);
+            asm.jump(&JE, done);
+            asm.emit(&[0x0f, 0xb6, 0x07]);
+            asm.emit(&[0x48, 0xff, 0xc7]);
+
+            let runs = nfa.byte_runs(index, &live);
+            let fallthrough = densest_target(&runs);
+            let cases: Vec<(u8, u8, usize)> = runs
+                .into_iter()
+                .filter(|&(_, _, target)| target != fallthrough)
+                .map(|(a, b, target)| (a, b, label(target)))
+                .collect();
+
+            if !cases.is_empty() {
+                let mut search = JitSearch {
+                    asm: &mut asm,
+                    fallthrough: label(fallthrough),
+                };
+
+                // Writing machine code cannot fail
+                search_runs(&mut search, &cases, 0, 255).unwrap();
+            }
+
+            asm.jmp(label(fallthrough));
+        }
+
+        // xorl %eax, %eax; ret
+        asm.bind(reject);
+        asm.emit(&[0x31, 0xc0, 0xc3]);
+        // movl $1, %eax; ret
+        asm.bind(accept);
+        asm.emit(&[0xb8, 1, 0, 0, 0, 0xc3]);
+
+        asm.finish()
+    }
+}
+
+/// Compare chain of `Nfa::compile_asm` as machine code, bytes matching no
+/// run jumping to `fallthrough`.
+struct JitSearch<'a> {
+    asm: &'a mut Assembler,
+    fallthrough: usize,
+}
+
+impl RunSearch for JitSearch<'_> {
+    type Target = usize;
+    type Branch = usize;
+
+    fn jump_if(&mut self, test: RunTest, target: usize) -> Result<(), Error> {
+        let asm = &mut *self.asm;
+
+        match test {
+            RunTest::Any => asm.jmp(target),
+            RunTest::Equal(a) => {
+                asm.cmp_eax(a);
+                asm.jump(&JE, target);
+            }
+            RunTest::Within(a, b) => {
+                // leal -a(%rax), %edx; cmpl $(b - a), %edx
+                asm.emit(&[0x8d, 0x90]);
+                asm.emit(&(-(a as i32)).to_le_bytes());
+                asm.emit(&[0x81, 0xfa]);
+                asm.emit(&((b - a) as u32).to_le_bytes());
+                asm.jump(&JBE, target);
+            }
+            RunTest::AtLeast(a) => {
+                asm.cmp_eax(a);
+                asm.jump(&JAE, target);
+            }
+            RunTest::AtMost(b) => {
+                asm.cmp_eax(b);
+                asm.jump(&JBE, target);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn split(&mut self, split: u8) -> Result<usize, Error> {
+        let right_label = self.asm.label();
+
+        self.asm.cmp_eax(split);
+        self.asm.jump(&JAE, right_label);
+
+        Ok(right_label)
+    }
+
+    fn split_else(&mut self, &right_label: &usize) -> Result<(), Error> {
+        self.asm.jmp(self.fallthrough);
+        self.asm.bind(right_label);
+
+        Ok(())
+    }
+
+    fn split_end(&mut self, _: usize) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Matcher;
+    use crate::{build, dfa::Dfa, testing};
+
+    #[test]
+    fn test_jit_against_dfa() {
+        let mut inputs = testing::random_inputs(2000);
+        let mut rng = StdRng::seed_from_u64(1);
+
+        // Arbitrary bytes reach the comparisons against every boundary
+        inputs.extend((0..2000).map(|_| {
+            let len = rng.gen_range(0..8);
+            (0..len).map(|_| rng.gen()).collect::<Vec<u8>>()
+        }));
+
+        for pattern in testing::PATTERNS {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+            let matcher = dfa.jit();
+
+            assert!(matches!(matcher, Matcher::Native(_)), "{pattern}");
+
+            for input in inputs.iter() {
+                assert_eq!(
+                    matcher.is_match(input),
+                    dfa.is_match(input),
+                    "{pattern} on {input:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use petgraph::graph::NodeIndex;
+use rustc_hash::FxHashMap;
+
+use crate::{bitset::BitSet, nfa::Nfa};
+
+/// Matcher running the subset construction on demand, only building the DFA
+/// states that the inputs actually reach. Built states are cached up to a
+/// limit, after which the cache is flushed and rebuilt from scratch, so
+/// patterns with huge DFAs stay usable with bounded memory.
+pub struct LazyDfa {
+    nfa: Nfa,
+    e_closure: FxHashMap<NodeIndex, BitSet<NodeIndex>>,
+    class_map: [u8; 256],
+    /// First byte of every class
+    class_bytes: Vec<u8>,
+    capacity: usize,
+    /// The DFA states built so far, as sets of NFA states
+    states: Vec<BitSet<NodeIndex>>,
+    ids: FxHashMap<BitSet<NodeIndex>, usize>,
+    accepting: Vec<bool>,
+    /// Next state at index state * classes + class, once computed
+    transitions: Vec<Option<usize>>,
+    flushes: usize,
+}
+
+impl LazyDfa {
+    /// Matcher for `nfa`, any automaton such as the output of `from_ast`,
+    /// caching at most `capacity` DFA states.
+    pub fn new(nfa: Nfa, capacity: usize) -> LazyDfa {
+        let mut class_map = [0; 256];
+        let mut class_bytes = Vec::new();
+
+        for (class, (a, b)) in nfa.byte_classes().into_iter().enumerate() {
+            class_map[a as usize..=b as usize].fill(class as u8);
+            class_bytes.push(a);
+        }
+
+        LazyDfa {
+            e_closure: nfa.e_closure(),
+            nfa,
+            class_map,
+            class_bytes,
+            capacity: capacity.max(1),
+            states: Vec::new(),
+            ids: FxHashMap::default(),
+            accepting: Vec::new(),
+            transitions: Vec::new(),
+            flushes: 0,
+        }
+    }
+
+    /// Tells whether the whole input is matched.
+    pub fn is_match(&mut self, input: &[u8]) -> bool {
+        let start = self.e_closure.get(&self.nfa.start).unwrap().clone();
+        let mut state = self.add_state(start);
+
+        for &c in input {
+            if self.states[state].is_empty() {
+                return false;
+            }
+
+            state = self.next(state, self.class_map[c as usize] as usize);
+        }
+
+        self.accepting[state]
+    }
+
+    /// Number of DFA states in the cache.
+    pub fn cached_states(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Number of times the cache was full and had to be flushed.
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+
+    fn next(&mut self, state: usize, class: usize) -> usize {
+        let index = state * self.class_bytes.len() + class;
+
+        if let Some(next) = self.transitions[index] {
+            return next;
+        }
+
+        let set = self.nfa.step_set(
+            &self.states[state],
+            self.class_bytes[class],
+            &self.e_closure,
+        );
+        let flushes = self.flushes;
+        let next = self.add_state(set);
+
+        // The transition is only worth remembering if its source survived
+        if self.flushes == flushes {
+            self.transitions[index] = Some(next);
+        }
+
+        next
+    }
+
+    fn add_state(&mut self, set: BitSet<NodeIndex>) -> usize {
+        if let Some(&state) = self.ids.get(&set) {
+            return state;
+        }
+
+        if self.states.len() == self.capacity {
+            self.states.clear();
+            self.ids.clear();
+            self.accepting.clear();
+            self.transitions.clear();
+            self.flushes += 1;
+        }
+
+        let state = self.states.len();
+
+        self.accepting.push(self.nfa.accepts_set(&set));
+        self.ids.insert(set.clone(), state);
+        self.states.push(set);
+        self.transitions
+            .extend(std::iter::repeat_n(None, self.class_bytes.len()));
+
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LazyDfa;
+    use crate::{
+        build,
+        dfa::Dfa,
+        nfa::Nfa,
+        parse,
+        testing::{random_inputs, PATTERNS},
+    };
+
+    #[test]
+    fn test_against_dfa() {
+        let inputs = random_inputs(500);
+
+        for pattern in PATTERNS {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+            let nfa = Nfa::from_ast(&parse(pattern).unwrap());
+
+            for capacity in [1, 2, 3, 10_000] {
+                let mut lazy = LazyDfa::new(nfa.clone(), capacity);
+
+                for input in &inputs {
+                    assert_eq!(
+                        lazy.is_match(input),
+                        dfa.is_match(input),
+                        "{pattern} with capacity {capacity} on {input:?}"
+                    );
+                    assert!(lazy.cached_states() <= capacity);
+                }
+
+                if capacity == 10_000 {
+                    assert_eq!(lazy.flushes(), 0, "{pattern}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_flushes() {
+        let nfa = Nfa::from_ast(&parse("(a|b)*a(a|b){3}").unwrap());
+
+        for capacity in 1..=3 {
+            let mut lazy = LazyDfa::new(nfa.clone(), capacity);
+
+            assert!(lazy.is_match(b"abbaabab"));
+            assert!(!lazy.is_match(b"abbabbbb"));
+            assert!(lazy.flushes() > 0, "capacity {capacity}");
+        }
+
+        // With room for a single state, the states after `a` and `ab` each
+        // evict the one before
+        let mut lazy = LazyDfa::new(nfa, 1);
+        lazy.is_match(b"ab");
+        assert_eq!(lazy.flushes(), 2);
+    }
+}
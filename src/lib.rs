@@ -0,0 +1,65 @@
+pub mod analysis;
+pub mod asm;
+pub mod ast;
+pub mod bitset;
+pub mod derivative;
+pub mod dfa;
+pub mod elimination;
+pub mod escape;
+pub mod glushkov;
+pub mod jit;
+pub mod lazy;
+pub mod nfa;
+pub mod parser;
+pub mod rust;
+pub mod sample;
+pub mod scanner;
+pub mod script;
+pub mod shift_and;
+pub mod simplify;
+pub mod simulate;
+pub mod table;
+pub mod thompson;
+pub mod trie;
+pub mod verilog;
+pub mod wat;
+
+#[cfg(test)]
+mod testing;
+
+use ast::Ast;
+use nfa::Nfa;
+use parser::{Error, Parser};
+use scanner::Scanner;
+
+pub fn parse(pattern: &str) -> Result<Ast, Error> {
+    let input: Vec<char> = pattern.chars().collect();
+
+    let mut scanner = Scanner::new(&input);
+    let parser = Parser::new(scanner.scan_tokens());
+
+    parser.parse()
+}
+
+/// DFA of `ast` before minimization. Alternations of literals are built as
+/// a trie, anything else with Thompson's construction, simplified, then the
+/// subset construction.
+pub fn determinize(ast: &Ast) -> Nfa {
+    determinize_within(ast, usize::MAX).unwrap()
+}
+
+/// Like `determinize`, giving up with `None` once a DFA, of the pattern or
+/// of an operand of `&` or `~`, would need more than `max_states` states.
+pub fn determinize_within(ast: &Ast, max_states: usize) -> Option<Nfa> {
+    match ast.literal_alternatives() {
+        Some(literals) => Nfa::from_literals_within(&literals, max_states),
+        None => Nfa::from_ast_within(ast, max_states)?
+            .simplify()
+            .reduce_to_dfa_within(max_states),
+    }
+}
+
+/// Runs the whole pipeline and returns the minimized DFA of `pattern`.
+pub fn build(pattern: &str) -> Result<Nfa, Error> {
+    Ok(determinize(&parse(pattern)?).minimize())
+}
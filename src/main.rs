@@ -1,15 +1,17 @@
-use clap::{Arg, Parser as ClapParser};
+use clap::{Parser as ClapParser, Subcommand, ValueEnum};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use regez::analysis::{Analysis, Literals};
+use regez::ast::Ast;
+use regez::dfa::Dfa;
+use regez::glushkov::Glushkov;
+use regez::nfa::{Nfa, Transition, ALPHABET_MAX, DFA_STATE_BUDGET, NFA_STATE_BUDGET};
+use regez::sample::Sampler;
+use regez::shift_and::SHIFT_AND_MAX_POSITIONS;
 
-use scanner::Scanner;
-use parser::Parser;
-
-use std::process::{Command, Stdio};
 use std::io::Write;
-
-mod bitset;
-mod nfa;
-mod parser;
-mod scanner;
+use std::ops::RangeInclusive;
+use std::process::{Command, Stdio};
+use std::time::Instant;
 
 fn render_graph(out_file: &str, content: &str) {
     let mut process = Command::new("dot")
@@ -27,30 +29,491 @@ fn render_graph(out_file: &str, content: &str) {
     process.wait().expect("failed to wait for end of process");
 }
 
+fn escape(bytes: &[u8]) -> String {
+    let escaped: Vec<u8> = bytes
+        .iter()
+        .flat_map(|&c| std::ascii::escape_default(c))
+        .collect();
+
+    format!("\"{}\"", String::from_utf8(escaped).unwrap())
+}
+
+/// Parses `pattern`, exiting with a diagnostic when it is invalid.
+fn parse(pattern: &str) -> Ast {
+    regez::parse(pattern).unwrap_or_else(|err| {
+        eprintln!("error: {}", err.report(pattern));
+        std::process::exit(2);
+    })
+}
+
+/// Exits with a diagnostic for a pattern whose DFA exceeds the budget.
+fn too_many_states(max_states: usize, hint: &str) -> ! {
+    eprintln!("error: the DFA of this pattern needs more than {max_states} states{hint}");
+    std::process::exit(2);
+}
+
+/// Exits with a diagnostic for two DFAs whose product exceeds the budget.
+fn too_many_product_states() -> ! {
+    eprintln!("error: comparing the DFAs needs more than {DFA_STATE_BUDGET} states");
+    std::process::exit(2);
+}
+
+/// Exits with a diagnostic for a pattern the bit-parallel backend cannot
+/// simulate.
+fn shift_and_unfit() -> ! {
+    eprintln!("error: shift-and needs at most {SHIFT_AND_MAX_POSITIONS} positions and no & or ~");
+    std::process::exit(2);
+}
+
+/// Runs the whole pipeline and returns the minimized DFA of `pattern`.
+fn build(pattern: &str) -> Nfa {
+    match regez::determinize_within(&parse(pattern), DFA_STATE_BUDGET) {
+        Some(dfa) => dfa.minimize(),
+        None => too_many_states(DFA_STATE_BUDGET, ""),
+    }
+}
+
 #[derive(ClapParser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    input: String,
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[arg(required = true)]
+    input: Option<String>,
 
     #[arg(short, long)]
     debug: bool,
+
+    /// Language of the generated matcher
+    #[arg(long, value_enum, default_value_t = Target::C)]
+    target: Target,
+
+    /// Style of the generated C code
+    #[arg(long, value_enum, default_value_t = Codegen::Goto)]
+    codegen: Codegen,
+
+    /// Emit a C API fed by chunks instead of `matches`
+    #[arg(long, conflicts_with = "codegen")]
+    streaming: bool,
+
+    /// Emit `search`, finding the leftmost match in a buffer, instead of `matches`
+    #[arg(long, conflicts_with_all = ["codegen", "streaming"])]
+    search: bool,
+
+    /// Prefix of the streaming API, or name of the Verilog module
+    #[arg(long, default_value = "regez")]
+    name: String,
+
+    /// How the NFA is built from the pattern
+    #[arg(long, value_enum, default_value_t = Construction::Thompson)]
+    nfa: Construction,
+
+    /// Most states the DFA may have
+    #[arg(long, default_value_t = DFA_STATE_BUDGET, value_parser = parse_max_states)]
+    max_states: usize,
+
+    /// What to emit instead of `matches` in C when the DFA would exceed --max-states
+    #[arg(long, value_enum, conflicts_with_all = ["search", "streaming"])]
+    fallback: Option<Fallback>,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Construction {
+    /// Fragments joined by epsilon transitions
+    Thompson,
+    /// One state per position of the pattern, without epsilon transitions
+    Glushkov,
+    /// No NFA: the DFA is built from the derivatives of the pattern
+    Derivatives,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Fallback {
+    /// C code simulating the Thompson NFA with bitsets
+    Nfa,
+    /// Bit-parallel C code, for patterns with at most 63 positions
+    ShiftAnd,
+    /// Shift-and when the pattern fits it, the NFA simulation otherwise
+    Auto,
+}
 
-    let input: Vec<char> = args.input.chars().collect();
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Target {
+    C,
+    Rust,
+    /// GNU assembler for x86-64 System V
+    Asm,
+    /// WebAssembly text format
+    Wat,
+    /// JavaScript module
+    Js,
+    /// TypeScript module
+    Ts,
+    /// Python module
+    Python,
+    /// Verilog module and its testbench
+    Verilog,
+}
 
-    let mut scanner = Scanner::new(&input);
-    let parser = Parser::new(scanner.scan_tokens());
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Codegen {
+    /// One label per state, jumping on range checks
+    Goto,
+    /// A transition table over byte classes run in a loop
+    Table,
+    /// The position automaton simulated with one bit per position
+    ShiftAnd,
+}
 
-    let nfa = parser.parse();
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Check whether two patterns accept the same language
+    Equiv {
+        left: String,
+        right: String,
 
-    if args.debug {
-        render_graph("stage1.png", &nfa.to_dot().unwrap());
+        /// Only check that every string matched by LEFT is matched by RIGHT
+        #[arg(long)]
+        subset: bool,
+    },
+    /// List the shortest strings accepted by a pattern
+    Examples {
+        pattern: String,
+
+        /// Number of strings to list
+        #[arg(short, default_value_t = 10)]
+        n: usize,
+
+        /// List rejected strings instead
+        #[arg(long)]
+        reject: bool,
+
+        /// Only build strings out of these ASCII characters
+        #[arg(long)]
+        alphabet: Option<String>,
+
+        /// Print the number of strings of each length up to N instead
+        #[arg(long, value_name = "N")]
+        len: Option<usize>,
+    },
+    /// Draw random strings accepted by a pattern, uniformly for each length
+    Sample {
+        pattern: String,
+
+        /// Number of strings to draw
+        #[arg(short, default_value_t = 10)]
+        n: usize,
+
+        /// Lengths to draw from, as A..B or A..=B
+        #[arg(long, default_value = "0..=16", value_parser = parse_len_range)]
+        len_range: RangeInclusive<usize>,
+
+        /// Seed of the random number generator
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Report facts about the language of a pattern, exiting with status 1
+    /// when it is empty
+    Analyze {
+        pattern: String,
+
+        /// Report the literals a search can skip ahead to instead
+        #[arg(long)]
+        literals: bool,
+
+        /// Compare the Thompson, Glushkov and derivative constructions instead
+        #[arg(long, conflicts_with = "literals")]
+        constructions: bool,
+    },
+    /// Print a simplified pattern accepting the same language
+    Normalize { pattern: String },
+}
+
+fn parse_max_states(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("the DFA needs at least one state".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_len_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse = |n: &str| n.parse::<usize>().map_err(|e| format!("{n:?}: {e}"));
+
+    if let Some((a, b)) = s.split_once("..=") {
+        Ok(parse(a)?..=parse(b)?)
+    } else if let Some((a, b)) = s.split_once("..") {
+        match parse(b)?.checked_sub(1) {
+            Some(b) => Ok(parse(a)?..=b),
+            None => Err(format!("empty range {s:?}")),
+        }
+    } else {
+        let n = parse(s)?;
+        Ok(n..=n)
+    }
+}
+
+fn equiv(left: &str, right: &str, subset: bool) -> bool {
+    let a = Dfa::from_nfa(&build(left));
+    let b = Dfa::from_nfa(&build(right));
+
+    let difference = if subset {
+        a.product_within(&b, |x, y| x && !y, DFA_STATE_BUDGET)
+    } else {
+        a.product_within(&b, |x, y| x != y, DFA_STATE_BUDGET)
+    };
+    let difference = difference.unwrap_or_else(|| too_many_product_states());
+
+    let Some(witness) = difference.shortest_match() else {
+        println!("{}", if subset { "subset" } else { "equivalent" });
+        return true;
+    };
+
+    let (matched, other) = if a.is_match(&witness) {
+        (left, right)
+    } else {
+        (right, left)
+    };
+
+    println!(
+        "{}: {} is matched by {} but not by {}",
+        if subset {
+            "not a subset"
+        } else {
+            "not equivalent"
+        },
+        escape(&witness),
+        matched,
+        other,
+    );
+
+    false
+}
+
+/// Builds `pattern` with every construction, printing the size of the NFAs
+/// before and after simplification and how long determinizing them takes,
+/// and checks that they accept the same language.
+fn constructions(pattern: &str) -> bool {
+    let ast = parse(pattern);
+    let thompson = Nfa::from_ast_within(&ast, DFA_STATE_BUDGET)
+        .unwrap_or_else(|| too_many_states(DFA_STATE_BUDGET, ""));
+    let glushkov =
+        Glushkov::new_within(&ast, NFA_STATE_BUDGET).map(|glushkov| Nfa::from_glushkov(&glushkov));
+    let mut dfas = Vec::new();
+
+    for (name, nfa) in [("thompson", Some(thompson)), ("glushkov", glushkov)] {
+        let Some(nfa) = nfa else {
+            println!("{name}: skipped, & and ~ are not supported, nor more than {NFA_STATE_BUDGET} positions");
+            continue;
+        };
+
+        let epsilon = nfa
+            .graph
+            .edge_weights()
+            .filter(|transition| **transition == Transition::Empty)
+            .count();
+
+        let start = Instant::now();
+        let simplified = nfa.simplify();
+        let dfa = simplified
+            .reduce_to_dfa_within(DFA_STATE_BUDGET)
+            .unwrap_or_else(|| too_many_states(DFA_STATE_BUDGET, ""));
+        let elapsed = start.elapsed();
+
+        println!(
+            "{name}: {} states, {} transitions ({epsilon} epsilon), simplified to {} states, {} transitions, determinized to {} states in {elapsed:?}",
+            nfa.graph.node_count(),
+            nfa.graph.edge_count(),
+            simplified.graph.node_count(),
+            simplified.graph.edge_count(),
+            dfa.graph.node_count()
+        );
+
+        dfas.push(Dfa::from_nfa(&dfa.minimize()));
+    }
+
+    // Derivatives give a DFA straight away
+    let start = Instant::now();
+    let dfa = Dfa::from_derivatives_within(&ast, DFA_STATE_BUDGET)
+        .unwrap_or_else(|| too_many_states(DFA_STATE_BUDGET, ""));
+    let elapsed = start.elapsed();
+
+    println!("derivatives: {} states in {elapsed:?}", dfa.state_count());
+
+    dfas.push(Dfa::from_nfa(&dfa.to_nfa().minimize()));
+
+    for dfa in dfas[1..].iter() {
+        let difference = dfas[0]
+            .product_within(dfa, |x, y| x != y, DFA_STATE_BUDGET)
+            .unwrap_or_else(|| too_many_product_states());
+
+        if let Some(witness) = difference.shortest_match() {
+            println!("not equivalent: {} tells them apart", escape(&witness));
+            return false;
+        }
+    }
+
+    println!("equivalent");
+    true
+}
+
+fn examples(pattern: &str, n: usize, reject: bool, alphabet: Option<String>, len: Option<usize>) {
+    let mut dfa = Dfa::from_nfa(&build(pattern));
+
+    if reject {
+        for accepting in dfa.accepting.iter_mut() {
+            *accepting = !*accepting;
+        }
+    }
+
+    let alphabet: Vec<u8> = match alphabet {
+        Some(alphabet) if !alphabet.is_ascii() => {
+            eprintln!("error: the alphabet can only hold ASCII characters");
+            std::process::exit(2);
+        }
+        Some(alphabet) => alphabet.into_bytes(),
+        None => (0..=ALPHABET_MAX).collect(),
+    };
+
+    if let Some(len) = len {
+        for (len, count) in dfa.count_by_length(&alphabet, len).iter().enumerate() {
+            println!("{len}: {count}");
+        }
+
+        return;
+    }
+
+    for string in dfa.shortlex(&alphabet).take(n) {
+        println!("{}", escape(&string));
+    }
+}
+
+fn sample(pattern: &str, n: usize, len_range: RangeInclusive<usize>, seed: Option<u64>) {
+    let dfa = Dfa::from_nfa(&build(pattern));
+    let sampler = Sampler::new(&dfa, *len_range.end());
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    // Every length holding at least one string is equally likely, then every
+    // string of that length
+    let lens = sampler.lengths(len_range);
+
+    if lens.is_empty() {
+        eprintln!("{pattern} accepts no string in the requested length range");
+        std::process::exit(1);
+    }
+
+    for _ in 0..n {
+        let len = lens[rng.gen_range(0..lens.len())];
+        println!("{}", escape(&sampler.sample(len, &mut rng).unwrap()));
+    }
+}
+
+/// NFA of `ast` built with the construction chosen by `--nfa`, Thompson's
+/// standing in for derivatives.
+fn build_nfa(ast: &Ast, args: &Args) -> Nfa {
+    let max_nfa_states = args.max_states.max(NFA_STATE_BUDGET);
+
+    match args.nfa {
+        Construction::Glushkov => match Glushkov::new_within(ast, max_nfa_states) {
+            Some(glushkov) => Nfa::from_glushkov(&glushkov),
+            None => {
+                eprintln!(
+                    "error: the Glushkov construction needs at most {max_nfa_states} positions and no & or ~"
+                );
+                std::process::exit(2);
+            }
+        },
+        Construction::Thompson | Construction::Derivatives => {
+            Nfa::from_ast_within(ast, args.max_states).unwrap_or_else(|| {
+                eprintln!(
+                    "error: the NFA of this pattern needs more than {max_nfa_states} states, or an operand of & or ~ a DFA of more than {} states",
+                    args.max_states
+                );
+                std::process::exit(2);
+            })
+        }
+    }
+}
+
+fn compile(pattern: &str, args: &Args) {
+    if !matches!(args.codegen, Codegen::Goto) && !matches!(args.target, Target::C) {
+        eprintln!("error: --codegen only applies to the C target");
+        std::process::exit(2);
+    }
+
+    if args.fallback.is_some() && !matches!(args.target, Target::C) {
+        eprintln!("error: --fallback only applies to the C target");
+        std::process::exit(2);
+    }
+
+    let ast = parse(pattern);
+
+    // Plain `matches` in C can be generated without a DFA
+    let matches_c = matches!(args.target, Target::C) && !args.search && !args.streaming;
+    let shift_and = || Glushkov::new_within(&ast, SHIFT_AND_MAX_POSITIONS);
+
+    if matches_c && matches!(args.codegen, Codegen::ShiftAnd) {
+        let Some(glushkov) = shift_and() else {
+            shift_and_unfit();
+        };
+
+        println!("{}", glushkov.compile_shift_and().unwrap());
+        return;
+    }
+
+    if args.debug && !matches!(args.nfa, Construction::Derivatives) {
+        let nfa = build_nfa(&ast, args);
+        let simplified = nfa.simplify();
+
+        eprintln!(
+            "simplified the NFA from {} to {} states and from {} to {} transitions",
+            nfa.graph.node_count(),
+            simplified.graph.node_count(),
+            nfa.graph.edge_count(),
+            simplified.graph.edge_count()
+        );
+        render_graph("stage1.png", &simplified.to_dot().unwrap());
     }
 
-    let dfa = nfa.reduce_to_dfa();
+    let dfa = match args.nfa {
+        Construction::Thompson => regez::determinize_within(&ast, args.max_states),
+        Construction::Glushkov => build_nfa(&ast, args)
+            .simplify()
+            .reduce_to_dfa_within(args.max_states),
+        Construction::Derivatives => {
+            Dfa::from_derivatives_within(&ast, args.max_states).map(|dfa| dfa.to_nfa())
+        }
+    };
+
+    let dfa = match dfa {
+        Some(dfa) => dfa,
+        None if !matches_c => too_many_states(args.max_states, "\nraise --max-states"),
+        None => {
+            // The fallbacks match the whole input, unlike the goto code, so
+            // they have to be asked for
+            let code = match args.fallback {
+                None => too_many_states(args.max_states, "\nraise --max-states, or use --fallback"),
+                Some(Fallback::Nfa) => build_nfa(&ast, args).simplify().compile_simulation(),
+                Some(Fallback::ShiftAnd) => match shift_and() {
+                    Some(glushkov) => glushkov.compile_shift_and(),
+                    None => shift_and_unfit(),
+                },
+                Some(Fallback::Auto) => match shift_and() {
+                    Some(glushkov) => glushkov.compile_shift_and(),
+                    None => build_nfa(&ast, args).simplify().compile_simulation(),
+                },
+            };
+
+            println!("{}", code.unwrap());
+            return;
+        }
+    };
 
     if args.debug {
         render_graph("stage2.png", &dfa.to_dot().unwrap());
@@ -62,5 +525,110 @@ fn main() {
         render_graph("stage3.png", &minimized_dfa.to_dot().unwrap());
     }
 
-    println!("{}", minimized_dfa.compile().unwrap());
+    let code = match args.target {
+        Target::C if args.search => {
+            Dfa::from_nfa(&minimized_dfa).compile_search(&Literals::new(&minimized_dfa))
+        }
+        Target::C if args.streaming => minimized_dfa.compile_streaming(&args.name),
+        Target::C => match args.codegen {
+            Codegen::Goto => minimized_dfa.compile(),
+            Codegen::Table => Dfa::from_nfa(&minimized_dfa).compile_table(),
+            Codegen::ShiftAnd => unreachable!(),
+        },
+        Target::Rust => minimized_dfa.compile_rust(),
+        Target::Asm => minimized_dfa.compile_asm(),
+        Target::Wat => Dfa::from_nfa(&minimized_dfa).compile_wat(),
+        Target::Js => Dfa::from_nfa(&minimized_dfa).compile_js(false),
+        Target::Ts => Dfa::from_nfa(&minimized_dfa).compile_js(true),
+        Target::Python => Dfa::from_nfa(&minimized_dfa).compile_python(),
+        Target::Verilog => {
+            let dfa = Dfa::from_nfa(&minimized_dfa);
+            let alphabet: Vec<u8> = (0..=ALPHABET_MAX).collect();
+
+            dfa.compile_verilog(&args.name).and_then(|module| {
+                let testbench = dfa.compile_verilog_testbench(&args.name, &alphabet, 16)?;
+                Ok(format!("{module}\n{testbench}"))
+            })
+        }
+    };
+
+    println!("{}", code.unwrap());
+}
+
+fn main() {
+    let args = Args::parse();
+
+    match args.command {
+        Some(Commands::Equiv {
+            left,
+            right,
+            subset,
+        }) => {
+            if !equiv(&left, &right, subset) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Examples {
+            pattern,
+            n,
+            reject,
+            alphabet,
+            len,
+        }) => examples(&pattern, n, reject, alphabet, len),
+        Some(Commands::Sample {
+            pattern,
+            n,
+            len_range,
+            seed,
+        }) => sample(&pattern, n, len_range, seed),
+        Some(Commands::Analyze {
+            pattern,
+            literals: true,
+            ..
+        }) => println!("{}", Literals::new(&build(&pattern))),
+        Some(Commands::Analyze {
+            pattern,
+            constructions: true,
+            ..
+        }) => {
+            if !constructions(&pattern) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Analyze { pattern, .. }) => {
+            let analysis = Analysis::new(&build(&pattern));
+            println!("{analysis}");
+
+            if analysis.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Normalize { pattern }) => {
+            println!("{}", Dfa::from_nfa(&build(&pattern)).to_regex());
+        }
+        None => compile(args.input.as_ref().unwrap(), &args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::equiv;
+
+    #[test]
+    fn test_equiv() {
+        assert!(equiv("(a|b)*", "(a*b*)*", false));
+        assert!(equiv("[a-c]+&~(b.*)", "[ac][a-c]*", false));
+        assert!(!equiv("a*b?", "(a|b)*", false));
+        assert!(!equiv("(a|b)*", "a*b?", false));
+        assert!(!equiv("a", "[]", false));
+    }
+
+    #[test]
+    fn test_subset() {
+        assert!(equiv("a*b?", "(a|b)*", true));
+        assert!(equiv("[]", "a", true));
+        assert!(equiv("abc", "abc", true));
+        assert!(!equiv("(a|b)*", "a*b?", true));
+        assert!(!equiv("a", "[]", true));
+    }
 }
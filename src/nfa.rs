@@ -1,20 +1,165 @@
-use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
+use rustc_hash::FxHashMap;
 use std::{
-    collections::{btree_map::Range, HashSet, VecDeque},
-    fmt::{write, Debug, Display, Error, Write},
+    collections::VecDeque,
+    fmt::{Debug, Display, Error, Write},
     hash::Hash,
-    os::linux::raw::stat,
-    slice::Windows,
 };
 
 use petgraph::{
-    algo::dijkstra,
-    graph::{DiGraph, Node, NodeIndex},
-    visit::{EdgeRef, IntoEdgeReferences, IntoEdgesDirected, IntoNodeReferences, NodeRef},
+    graph::{DiGraph, NodeIndex},
+    visit::{EdgeRef, IntoNodeReferences},
     Direction,
 };
 
-use crate::bitset::BitSet;
+use crate::{
+    bitset::BitSet,
+    escape::{c_char, dot_string, label_byte},
+};
+
+/// Largest byte matched by `.` and by a complement.
+pub const ALPHABET_MAX: u8 = 127;
+
+/// Default for the most DFA states built before giving up.
+pub const DFA_STATE_BUDGET: usize = 10_000;
+
+/// Most NFA states, or Glushkov positions, built before giving up, unless
+/// the DFA budget is higher. A lower DFA budget leaves the NFA alone, since
+/// the NFA simulation never determinizes.
+pub const NFA_STATE_BUDGET: usize = 10_000;
+
+/// Most bytes tested by a `switch` in the C output before falling back to a
+/// binary search.
+const SWITCH_MAX_CASES: usize = 16;
+
+fn c_label(target: Option<NodeIndex>) -> String {
+    match target {
+        Some(state) => format!("s{}", state.index()),
+        None => "end".to_string(),
+    }
+}
+
+/// Target taking the most bytes out of runs from `Nfa::byte_runs`, which
+/// dispatches leave as their fallthrough.
+pub fn densest_target(runs: &[(u8, u8, Option<NodeIndex>)]) -> Option<NodeIndex> {
+    let mut sizes: FxHashMap<Option<NodeIndex>, usize> = FxHashMap::default();
+    let mut densest = None;
+
+    for &(a, b, target) in runs {
+        let size = sizes.entry(target).or_default();
+        *size += (b - a) as usize + 1;
+
+        if *size > sizes.get(&densest).copied().unwrap_or(0) {
+            densest = target;
+        }
+    }
+
+    densest
+}
+
+/// Test of the byte against a run, leaving out the bounds already known.
+#[derive(Debug, Clone, Copy)]
+pub enum RunTest {
+    Any,
+    Equal(u8),
+    AtLeast(u8),
+    AtMost(u8),
+    Within(u8, u8),
+}
+
+/// Code emitted by `search_runs` for one of the backends.
+pub trait RunSearch {
+    /// Where a run jumps to
+    type Target: Copy;
+    /// What `split` hands over to `split_else` and `split_end`
+    type Branch;
+
+    /// Jumps to `target` when the byte passes `test`.
+    fn jump_if(&mut self, test: RunTest, target: Self::Target) -> Result<(), Error>;
+
+    /// Starts testing the bytes below `split`.
+    fn split(&mut self, split: u8) -> Result<Self::Branch, Error>;
+
+    /// Starts testing the other bytes, the ones below `split` having
+    /// matched no run.
+    fn split_else(&mut self, branch: &Self::Branch) -> Result<(), Error>;
+
+    fn split_end(&mut self, branch: Self::Branch) -> Result<(), Error>;
+}
+
+/// Binary search over sorted runs, knowing that `lo <= c <= hi`. Bytes
+/// outside of every run fall out of the tested conditions.
+pub fn search_runs<S: RunSearch>(
+    sink: &mut S,
+    runs: &[(u8, u8, S::Target)],
+    lo: u8,
+    hi: u8,
+) -> Result<(), Error> {
+    if let [(a, b, target)] = *runs {
+        let test = match (a > lo, b < hi) {
+            (false, false) => RunTest::Any,
+            _ if a == b => RunTest::Equal(a),
+            (true, true) => RunTest::Within(a, b),
+            (true, false) => RunTest::AtLeast(a),
+            (false, true) => RunTest::AtMost(b),
+        };
+
+        return sink.jump_if(test, target);
+    }
+
+    let (left, right) = runs.split_at(runs.len() / 2);
+    let split = right[0].0;
+
+    let branch = sink.split(split)?;
+    search_runs(sink, left, lo, split - 1)?;
+    sink.split_else(&branch)?;
+    search_runs(sink, right, split, hi)?;
+    sink.split_end(branch)
+}
+
+/// Nested `if` statements of the goto backend.
+struct CSearch<'a> {
+    s: &'a mut String,
+    depth: usize,
+}
+
+impl RunSearch for CSearch<'_> {
+    type Target = Option<NodeIndex>;
+    type Branch = ();
+
+    fn jump_if(&mut self, test: RunTest, target: Self::Target) -> Result<(), Error> {
+        let indent = "\t".repeat(self.depth);
+        let condition = match test {
+            RunTest::Any => return writeln!(self.s, "{indent}goto {};", c_label(target)),
+            RunTest::Equal(a) => format!("c == {}", c_char(a)),
+            RunTest::Within(a, b) => format!("c >= {} && c <= {}", c_char(a), c_char(b)),
+            RunTest::AtLeast(a) => format!("c >= {}", c_char(a)),
+            RunTest::AtMost(b) => format!("c <= {}", c_char(b)),
+        };
+
+        writeln!(self.s, "{indent}if ({condition}) goto {};", c_label(target))
+    }
+
+    fn split(&mut self, split: u8) -> Result<(), Error> {
+        writeln!(
+            self.s,
+            "{}if (c < {}) {{",
+            "\t".repeat(self.depth),
+            c_char(split)
+        )?;
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    fn split_else(&mut self, _: &()) -> Result<(), Error> {
+        writeln!(self.s, "{}}} else {{", "\t".repeat(self.depth - 1))
+    }
+
+    fn split_end(&mut self, _: ()) -> Result<(), Error> {
+        self.depth -= 1;
+        writeln!(self.s, "{}}}", "\t".repeat(self.depth))
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Transition {
@@ -29,22 +174,21 @@ pub enum State {
     NotAccepting,
 }
 
-// FIXME move elsewhere
-fn merge_ranges(mut ranges: Vec<(u8, u8)>) -> Vec<(u8, u8)> {
+pub fn merge_ranges(mut ranges: Vec<(u8, u8)>) -> Vec<(u8, u8)> {
     if ranges.is_empty() {
         return ranges;
     }
 
     // Sort ranges by the starting value
-    ranges.sort_by(|a, b| a.0.cmp(&b.0));
+    ranges.sort_by_key(|r| r.0);
 
     let mut merged_ranges = vec![];
 
     // Start with the first range
-    let mut current_range = ranges[0].clone();
+    let mut current_range = ranges[0];
 
     for next_range in ranges.into_iter().skip(1) {
-        if next_range.0 <= current_range.1 {
+        if next_range.0 as u16 <= current_range.1 as u16 + 1 {
             // If the next range overlaps or is consecutive, merge it
             current_range.1 = current_range.1.max(next_range.1);
         } else {
@@ -60,17 +204,74 @@ fn merge_ranges(mut ranges: Vec<(u8, u8)>) -> Vec<(u8, u8)> {
     merged_ranges
 }
 
+/// Bytes of the alphabet that are not covered by `ranges`, which must be
+/// sorted and disjoint.
+pub fn complement_ranges(ranges: &[(u8, u8)]) -> Vec<(u8, u8)> {
+    let mut res = Vec::new();
+    let mut next: u16 = 0;
+
+    for &(a, b) in ranges {
+        if a > ALPHABET_MAX {
+            break;
+        }
+
+        if a as u16 > next {
+            res.push((next as u8, a - 1));
+        }
+
+        next = b as u16 + 1;
+    }
+
+    if next <= ALPHABET_MAX as u16 {
+        res.push((next as u8, ALPHABET_MAX));
+    }
+
+    res
+}
+
+impl Transition {
+    pub fn from_ranges(ranges: Vec<(u8, u8)>) -> Transition {
+        let ranges = merge_ranges(ranges);
+
+        if ranges.len() == 1 {
+            Transition::Range(ranges[0].0, ranges[0].1)
+        } else {
+            Transition::RangeList(ranges)
+        }
+    }
+
+    pub fn ranges(&self) -> Vec<(u8, u8)> {
+        match self {
+            Transition::Range(a, b) => vec![(*a, *b)],
+            Transition::RangeList(l) => l.clone(),
+            Transition::Empty => vec![],
+        }
+    }
+
+    pub fn contains(&self, c: u8) -> bool {
+        match self {
+            Transition::Range(a, b) => *a <= c && c <= *b,
+            Transition::RangeList(l) => l.iter().any(|&(a, b)| a <= c && c <= b),
+            Transition::Empty => false,
+        }
+    }
+}
+
 impl Display for Transition {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Transition::Range(a, b) if *a == 0 && *b == 127 => write!(f, ".")?,
-            Transition::Range(a, b) if *a == *b => write!(f, "'{}'", *a as char)?,
-            Transition::Range(a, b) => write!(f, "[{}-{}]", *a as char, *b as char)?,
+            Transition::Range(a, b) if *a == 0 && *b == ALPHABET_MAX => write!(f, ".")?,
+            Transition::Range(a, b) if *a == *b => write!(f, "'{}'", label_byte(*a))?,
+            Transition::Range(a, b) => write!(f, "[{}-{}]", label_byte(*a), label_byte(*b))?,
             Transition::RangeList(l) => {
                 write!(f, "[")?;
 
                 for (a, b) in l.iter() {
-                    write!(f, "{}-{}", *a as char, *b as char)?;
+                    if a == b {
+                        write!(f, "{}", label_byte(*a))?;
+                    } else {
+                        write!(f, "{}-{}", label_byte(*a), label_byte(*b))?;
+                    }
                 }
 
                 write!(f, "]")?;
@@ -82,17 +283,7 @@ impl Display for Transition {
     }
 }
 
-// pub fn convert_to_dfa(nfa: &mut Nfa, start: NodeIndex) {
-//     let q0 = emclosure(HashSet::from([start]));
-//     let q = q0;
-//     let mut work_list = Vec::from([q0]);
-
-//     while !work_list.is_empty() {
-
-//     }
-// }
-
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Nfa {
     pub graph: DiGraph<State, Transition>,
     pub start: NodeIndex,
@@ -137,15 +328,13 @@ impl Nfa {
                 continue;
             }
 
-            let neighbors: Vec<NodeIndex> = self.graph.neighbors(node).collect();
-            for neighbor in neighbors {
-                let transition = self
-                    .graph
-                    .edges_connecting(node, neighbor)
-                    .nth(0)
-                    .unwrap()
-                    .weight()
-                    .clone();
+            let edges: Vec<(NodeIndex, Transition)> = self
+                .graph
+                .edges(node)
+                .map(|edge| (edge.target(), edge.weight().clone()))
+                .collect();
+
+            for (neighbor, transition) in edges {
                 if let Some(&neighbor_clone) = mapping.get(&neighbor) {
                     let node_clone = *mapping.get(&node).unwrap();
                     self.add_transition(node_clone, neighbor_clone, transition);
@@ -159,81 +348,213 @@ impl Nfa {
             }
         }
 
-        (*mapping.get(&s1).unwrap(), *mapping.get(&s2).unwrap())
+        // s2 is never reached when the fragment matches nothing
+        let end = *mapping.entry(s2).or_insert_with(|| self.add_state());
+
+        (*mapping.get(&s1).unwrap(), end)
+    }
+
+    /// Copies `other` into this automaton as a fragment whose accepting
+    /// states all lead to a single new end state.
+    pub fn embed(&mut self, other: &Nfa) -> (NodeIndex, NodeIndex) {
+        let mapping: Vec<NodeIndex> = other
+            .graph
+            .node_indices()
+            .map(|_| self.add_state())
+            .collect();
+        let end = self.add_state();
+
+        for edge in other.graph.edge_references() {
+            self.add_transition(
+                mapping[edge.source().index()],
+                mapping[edge.target().index()],
+                edge.weight().clone(),
+            );
+        }
+
+        for (index, state) in other.graph.node_references() {
+            if *state == State::Accepting {
+                self.add_e_transition(mapping[index.index()], end);
+            }
+        }
+
+        (mapping[other.start.index()], end)
+    }
+
+    /// Splits the byte space into ranges that no transition cuts through, so
+    /// every byte of a range behaves the same in every state.
+    pub fn byte_classes(&self) -> Vec<(u8, u8)> {
+        let mut boundaries = [false; 257];
+        boundaries[0] = true;
+        boundaries[ALPHABET_MAX as usize + 1] = true;
+        boundaries[256] = true;
+
+        for edge in self.graph.edge_references() {
+            for (a, b) in edge.weight().ranges() {
+                boundaries[a as usize] = true;
+                boundaries[b as usize + 1] = true;
+            }
+        }
+
+        let mut classes = Vec::new();
+        let mut start = 0;
+
+        for (i, &boundary) in boundaries.iter().enumerate().skip(1) {
+            if boundary {
+                classes.push((start as u8, (i - 1) as u8));
+                start = i;
+            }
+        }
+
+        classes
+    }
+
+    /// Follows the transition taken on `c`, assuming the automaton is deterministic.
+    pub fn step(&self, state: NodeIndex, c: u8) -> Option<NodeIndex> {
+        self.graph
+            .edges(state)
+            .find(|edge| edge.weight().contains(c))
+            .map(|edge| edge.target())
+    }
+
+    /// States reachable from the start that can still reach an accepting state.
+    pub fn live_states(&self) -> BitSet<NodeIndex> {
+        let mut reachable: BitSet<NodeIndex> = BitSet::empty(self.graph.node_count());
+        let mut stack = vec![self.start];
+        reachable.insert(self.start.index());
+
+        while let Some(node) = stack.pop() {
+            for next in self.graph.neighbors_directed(node, Direction::Outgoing) {
+                if reachable.insert(next.index()) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        let mut productive: BitSet<NodeIndex> = BitSet::empty(self.graph.node_count());
+        let mut stack: Vec<NodeIndex> = self
+            .graph
+            .node_references()
+            .filter(|(_, state)| **state == State::Accepting)
+            .map(|(index, _)| index)
+            .collect();
+
+        for node in stack.iter() {
+            productive.insert(node.index());
+        }
+
+        while let Some(node) = stack.pop() {
+            for prev in self.graph.neighbors_directed(node, Direction::Incoming) {
+                if productive.insert(prev.index()) {
+                    stack.push(prev);
+                }
+            }
+        }
+
+        reachable.intersection_inplace(&productive);
+        reachable
     }
 
     pub fn reduce_to_dfa(&self) -> Nfa {
-        let mut e_closure = self.e_closure();
+        self.reduce_to_dfa_within(usize::MAX).unwrap()
+    }
+
+    /// Subset construction giving up with `None` once the DFA would need
+    /// more than `max_states` states.
+    pub fn reduce_to_dfa_within(&self, max_states: usize) -> Option<Nfa> {
+        let e_closure = self.e_closure();
+        let classes = self.byte_classes();
         let mut dfa = Nfa::new();
         let mut node_map: FxHashMap<BitSet<NodeIndex>, NodeIndex> = FxHashMap::default();
         let q0 = e_closure.get(&self.start).unwrap().clone();
 
+        if max_states == 0 {
+            return None;
+        }
+
         {
             let q0_index = dfa.add_state();
 
             node_map.insert(q0.clone(), q0_index);
             dfa.set_start(q0_index);
 
-            if q0
-                .iter()
-                .any(|i| self.graph[NodeIndex::new(i)] == State::Accepting)
-            {
+            if self.accepts_set(&q0) {
                 dfa.make_accepting(q0_index);
             }
         }
 
-        let mut work_list = VecDeque::from([q0.clone()]);
+        let mut work_list = VecDeque::from([q0]);
 
         while let Some(q) = work_list.pop_front() {
-            // FIXME inefficient
-            let mut mapping: FxHashMap<Transition, BitSet<NodeIndex>> = FxHashMap::default();
-
-            for el in q.iter() {
-                for edge in self
-                    .graph
-                    .edges_directed(NodeIndex::new(el), Direction::Outgoing)
-                    .filter(|edge| *edge.weight() != Transition::Empty)
-                {
-                    if !mapping.contains_key(edge.weight()) {
-                        mapping.insert(
-                            edge.weight().clone(),
-                            BitSet::empty(self.graph.node_count()),
-                        );
-                    }
+            let q_index = *node_map.get(&q).unwrap();
+            let mut mapping: FxHashMap<NodeIndex, Vec<(u8, u8)>> = FxHashMap::default();
 
-                    mapping
-                        .get_mut(edge.weight())
-                        .unwrap()
-                        .union_inplace(e_closure.get_mut(&edge.target()).unwrap());
+            for &(a, b) in classes.iter() {
+                let t = self.step_set(&q, a, &e_closure);
+
+                if t.is_empty() {
+                    continue;
                 }
-            }
 
-            for (weight, t) in mapping {
-                if !node_map.contains_key(&t) {
-                    let node_idx = dfa.add_state();
-                    node_map.insert(t.clone(), node_idx);
+                let t_index = match node_map.get(&t) {
+                    Some(&t_index) => t_index,
+                    None if dfa.graph.node_count() >= max_states => return None,
+                    None => {
+                        let node_idx = dfa.add_state();
+                        node_map.insert(t.clone(), node_idx);
+
+                        if self.accepts_set(&t) {
+                            dfa.make_accepting(node_idx);
+                        }
 
-                    if t.iter()
-                        .any(|i| self.graph[NodeIndex::new(i)] == State::Accepting)
-                    {
-                        dfa.make_accepting(node_idx);
+                        work_list.push_back(t);
+                        node_idx
                     }
+                };
 
-                    work_list.push_back(t.clone());
-                }
+                mapping.entry(t_index).or_default().push((a, b));
+            }
 
-                if let Some(q_index) = node_map.get(&q) {
-                    if let Some(t_index) = node_map.get(&t) {
-                        dfa.add_transition(*q_index, *t_index, weight);
-                    }
-                }
+            for (t_index, ranges) in mapping {
+                dfa.add_transition(q_index, t_index, Transition::from_ranges(ranges));
+            }
+        }
+
+        Some(dfa)
+    }
+
+    /// States reached from the set `q` on `c`, closed under epsilon
+    /// transitions with `e_closure`.
+    pub fn step_set(
+        &self,
+        q: &BitSet<NodeIndex>,
+        c: u8,
+        e_closure: &FxHashMap<NodeIndex, BitSet<NodeIndex>>,
+    ) -> BitSet<NodeIndex> {
+        let mut t = BitSet::empty(self.graph.node_count());
+
+        for el in q.iter() {
+            for edge in self
+                .graph
+                .edges_directed(NodeIndex::new(el), Direction::Outgoing)
+                .filter(|edge| edge.weight().contains(c))
+            {
+                t.union_inplace(e_closure.get(&edge.target()).unwrap());
             }
         }
 
-        dfa
+        t
+    }
+
+    /// Whether the set `q` holds an accepting state.
+    pub fn accepts_set(&self, q: &BitSet<NodeIndex>) -> bool {
+        q.iter()
+            .any(|i| self.graph[NodeIndex::new(i)] == State::Accepting)
     }
 
-    fn e_closure(&self) -> FxHashMap<NodeIndex, BitSet<NodeIndex>> {
+    /// States reachable from each state through epsilon transitions alone,
+    /// itself included.
+    pub fn e_closure(&self) -> FxHashMap<NodeIndex, BitSet<NodeIndex>> {
         let mut res: FxHashMap<NodeIndex, BitSet<NodeIndex>> = FxHashMap::default();
 
         for n in self.graph.node_indices() {
@@ -258,9 +579,16 @@ impl Nfa {
             for edge in self.graph.edges_directed(n, Direction::Incoming) {
                 if *edge.weight() == Transition::Empty {
                     let m = edge.source();
-                    // Backpropagate
-                    work_list.insert(m.index());
-                    res.get_mut(&m).unwrap().union_inplace(&t);
+                    let closure = res.get_mut(&m).unwrap();
+                    let before = closure.clone();
+
+                    // Backpropagate, revisiting m only if its closure grew so
+                    // that epsilon cycles terminate
+                    closure.union_inplace(&t);
+
+                    if *closure != before {
+                        work_list.insert(m.index());
+                    }
                 }
             }
         }
@@ -269,8 +597,15 @@ impl Nfa {
     }
 
     pub fn minimize(&self) -> Nfa {
-        let mut accepting_set: BitSet<NodeIndex> = BitSet::empty(self.graph.node_count());
         let mut res = Nfa::new();
+        let live = self.live_states();
+
+        if !live.contains(self.start.index()) {
+            res.add_state();
+            return res;
+        }
+
+        let mut accepting_set: BitSet<NodeIndex> = BitSet::empty(self.graph.node_count());
 
         for (i, s) in self.graph.node_references() {
             if *s == State::Accepting {
@@ -278,120 +613,136 @@ impl Nfa {
             }
         }
 
-        let non_accepting_set = accepting_set.complement();
-
-        let mut P = HashSet::from([accepting_set, non_accepting_set]);
-        let mut W: Vec<_> = P.clone().into_iter().collect();
+        let mut non_accepting_set = accepting_set.complement();
+        non_accepting_set.intersection_inplace(&live);
+        accepting_set.intersection_inplace(&live);
+
+        // Transition function, one row per byte class, and its inverse
+        let classes = self.byte_classes();
+        let delta: Vec<Vec<Option<NodeIndex>>> = classes
+            .iter()
+            .map(|&(a, _)| {
+                self.graph
+                    .node_indices()
+                    .map(|node| self.step(node, a))
+                    .collect()
+            })
+            .collect();
+        let inverse: Vec<Vec<Vec<usize>>> = delta
+            .iter()
+            .map(|row| {
+                let mut inverse = vec![Vec::new(); self.graph.node_count()];
+
+                for (node, target) in row.iter().enumerate() {
+                    if let Some(target) = target {
+                        inverse[target.index()].push(node);
+                    }
+                }
 
-        let mut alphabet = FxHashSet::default();
-        for s in self.graph.node_indices() {
-            for edge in self.graph.edges_directed(s, Direction::Outgoing) {
-                alphabet.insert(edge.weight().clone());
+                inverse
+            })
+            .collect();
+
+        // Hopcroft's refinement: a block is split by the states moving into a
+        // splitter on some byte class, and only the smaller half needs to be
+        // a splitter in turn unless the block was still waiting to be one
+        let mut blocks: Vec<Vec<usize>> = [accepting_set, non_accepting_set]
+            .iter()
+            .filter(|set| !set.is_empty())
+            .map(|set| set.iter().collect())
+            .collect();
+        let mut block_of = vec![None; self.graph.node_count()];
+
+        for (i, block) in blocks.iter().enumerate() {
+            for &state in block {
+                block_of[state] = Some(i);
             }
         }
 
-        while let Some(A) = W.pop() {
-            for c in alphabet.iter() {
-                let mut X: BitSet<NodeIndex> = BitSet::empty(self.graph.node_count());
+        let mut work_list: Vec<usize> = (0..blocks.len()).collect();
+        let mut waiting = vec![true; blocks.len()];
 
-                for node in self.graph.node_indices() {
-                    if let Some(transition_function) =
-                        self.graph.edges_directed(node, Direction::Outgoing).find(|e| e.weight() == c)
-                    {
-                        if A.contains(transition_function.target().index()) {
-                            X.insert(node.index());
+        while let Some(splitter) = work_list.pop() {
+            waiting[splitter] = false;
+            let splitter = blocks[splitter].clone();
+
+            for inverse in inverse.iter() {
+                let mut moving: FxHashMap<usize, Vec<usize>> = FxHashMap::default();
+
+                for &state in splitter.iter() {
+                    for &prev in inverse[state].iter() {
+                        if let Some(block) = block_of[prev] {
+                            moving.entry(block).or_default().push(prev);
                         }
                     }
                 }
 
-                for Y in P.clone().iter() {
-                    let difference = {
-                        let mut set = Y.clone();
-                        set.difference_inplace(&X);
-                        set
-                    };
-
-                    let intersection = {
-                        let mut set = Y.clone();
-                        set.intersection_inplace(&X);
-                        set
-                    };
-
-                    if !intersection.is_empty() && !difference.is_empty() {
-                        P.remove(Y);
-                        P.insert(difference.clone());
-                        P.insert(intersection.clone());
-
-                        if W.contains(Y) {
-                            W.remove(W.iter().position(|x| *x == *Y).unwrap());
-                            W.push(intersection);
-                            W.push(difference);
-                        } else {
-                            if intersection.iter().count() <= difference.iter().count() {
-                                W.push(intersection);
-                            } else {
-                                W.push(difference);
-                            }
-                        }
+                for (block, states) in moving {
+                    if states.len() == blocks[block].len() {
+                        continue;
+                    }
+
+                    let new_block = blocks.len();
+
+                    for &state in states.iter() {
+                        block_of[state] = Some(new_block);
+                    }
+
+                    blocks[block].retain(|&state| block_of[state] == Some(block));
+                    blocks.push(states);
+
+                    if waiting[block] || blocks[new_block].len() <= blocks[block].len() {
+                        work_list.push(new_block);
+                        waiting.push(true);
+                    } else {
+                        work_list.push(block);
+                        waiting[block] = true;
+                        waiting.push(false);
                     }
                 }
             }
         }
 
-        // Construct new DFA
+        // Construct new DFA, numbering states in breadth-first order so that
+        // equivalent inputs produce identical outputs
 
         let mut mapping = FxHashMap::default();
+        let start_block = block_of[self.start.index()].unwrap();
+        let start_id = res.add_state();
+        mapping.insert(start_block, start_id);
+        res.set_start(start_id);
 
-        for new_state in P.iter() {
-            let state_id = res.add_state();
-            mapping.insert(new_state.clone(), state_id);
-
-            for state in new_state.iter().take(1) {
-                let state = NodeIndex::new(state);
+        let mut queue = VecDeque::from([start_block]);
 
-                if state == self.start {
-                    res.set_start(state_id);
-                }
+        while let Some(block) = queue.pop_front() {
+            let state = NodeIndex::new(blocks[block][0]);
+            let state_id = *mapping.get(&block).unwrap();
 
-                if self.graph[state] == State::Accepting {
-                    res.make_accepting(state_id);
-                }
+            if self.graph[state] == State::Accepting {
+                res.make_accepting(state_id);
             }
-        }
-
-        for new_state in P.iter() {
-            let mut classes = FxHashMap::default();
-
-            for state in new_state.iter() {
-                let state = NodeIndex::new(state);
-                for edge in self.graph.edges_directed(state, Direction::Outgoing) {
-                    let target_state = P
-                        .iter()
-                        .find(|s| s.contains(edge.target().index()))
-                        .unwrap();
 
-                    match edge.weight() {
-                        Transition::Range(a, b) => {
-                            let new_edge = *mapping.get(target_state).unwrap();
+            let mut edges: Vec<(usize, Vec<(u8, u8)>)> = Vec::new();
 
-                            if !classes.contains_key(&new_edge) {
-                                classes.insert(new_edge, Vec::new());
-                            }
+            for (row, &(a, b)) in delta.iter().zip(classes.iter()) {
+                let Some(target_block) = row[state.index()].and_then(|t| block_of[t.index()])
+                else {
+                    continue;
+                };
 
-                            let vec = classes.get_mut(&new_edge).unwrap();
-                            vec.push((*a, *b));
-                        }
-                        _ => todo!(),
-                    };
+                match edges.iter_mut().find(|(t, _)| *t == target_block) {
+                    Some((_, ranges)) => ranges.push((a, b)),
+                    None => edges.push((target_block, vec![(a, b)])),
                 }
             }
 
-            for (node, edge) in classes {
-                res.graph.update_edge(
-                    *mapping.get(new_state).unwrap(),
-                    node,
-                    Transition::RangeList(merge_ranges(edge)),
-                );
+            for (target_block, ranges) in edges {
+                let target_id = *mapping.entry(target_block).or_insert_with(|| {
+                    queue.push_back(target_block);
+                    res.add_state()
+                });
+
+                res.add_transition(state_id, target_id, Transition::from_ranges(ranges));
             }
         }
 
@@ -407,56 +758,101 @@ impl Nfa {
 
         for (index, state) in self.graph.node_references() {
             if *state == State::Accepting {
-                write!(&mut s, "\t\"{}\" [shape = doublecircle];\n", index.index())?;
+                writeln!(&mut s, "\t\"{}\" [shape = doublecircle];", index.index())?;
             }
         }
 
         for edge in self.graph.edge_references() {
-            write!(
+            writeln!(
                 &mut s,
-                "\t\"{}\" -> \"{}\" [label = \"{}\"];\n",
+                "\t\"{}\" -> \"{}\" [label = \"{}\"];",
                 edge.source().index(),
                 edge.target().index(),
-                edge.weight(),
+                dot_string(&edge.weight().to_string()),
             )?;
         }
 
-        s.push_str("}");
+        s.push('}');
 
         Ok(s)
     }
 
-    fn c_condition(&self, t: &Transition) -> Result<String, Error> {
-        let mut res = String::new();
-        match t {
-            Transition::Range(a, b) if *a == 0 && *b == 127 => (),
-            Transition::Range(a, b) if *a == *b => write!(&mut res, "if (c == '{}') ", *a as char)?,
-            Transition::Range(a, b) => write!(
-                &mut res,
-                "if (c >= '{}' && c <= '{}') ",
-                *a as char, *b as char,
-            )?,
-            Transition::RangeList(l) => {
-                write!(&mut res, "if (")?;
+    /// Splits the bytes into sorted runs by the state they lead to from
+    /// `state`, `None` standing for no transition or one to a dead state.
+    pub fn byte_runs(
+        &self,
+        state: NodeIndex,
+        live: &BitSet<NodeIndex>,
+    ) -> Vec<(u8, u8, Option<NodeIndex>)> {
+        let mut targets = [None; 256];
+
+        for edge in self.graph.edges_directed(state, Direction::Outgoing) {
+            if !live.contains(edge.target().index()) {
+                continue;
+            }
 
-                for (index, &(a, b)) in l.iter().enumerate() {
-                    if a == b {
-                        write!(&mut res, "(c == '{}')", a as char)?;
-                    } else if !(a == 0 && b == 127) {
-                        write!(&mut res, "(c >= '{}' && c <= '{}')", a as char, b as char)?;
-                    }
+            for (a, b) in edge.weight().ranges() {
+                for c in a..=b {
+                    targets[c as usize] = Some(edge.target());
+                }
+            }
+        }
+
+        let mut runs: Vec<(u8, u8, Option<NodeIndex>)> = Vec::new();
 
-                    if index < l.len() - 1 {
-                        write!(&mut res, " || ")?;
+        for (c, &target) in targets.iter().enumerate() {
+            match runs.last_mut() {
+                Some((_, b, t)) if *t == target => *b = c as u8,
+                _ => runs.push((c as u8, c as u8, target)),
+            }
+        }
+
+        runs
+    }
+
+    /// Jumps on the current byte. The target covering the most bytes is left
+    /// as the fallthrough, the others are tested with a `switch` when they
+    /// cover few bytes and with a binary search over the runs otherwise.
+    fn c_dispatch(
+        &self,
+        s: &mut String,
+        runs: &[(u8, u8, Option<NodeIndex>)],
+    ) -> Result<(), Error> {
+        let fallthrough = densest_target(runs);
+        let cases: Vec<(u8, u8, Option<NodeIndex>)> = runs
+            .iter()
+            .copied()
+            .filter(|&(_, _, target)| target != fallthrough)
+            .collect();
+        let case_count: usize = cases.iter().map(|&(a, b, _)| (b - a) as usize + 1).sum();
+
+        if case_count > SWITCH_MAX_CASES {
+            search_runs(&mut CSearch { s, depth: 1 }, &cases, 0, 255)?;
+        } else if !cases.is_empty() {
+            let mut targets: Vec<Option<NodeIndex>> = Vec::new();
+
+            for &(_, _, target) in cases.iter() {
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+
+            s.push_str("\tswitch (c) {\n");
+
+            for target in targets {
+                for &(a, b, _) in cases.iter().filter(|&&(_, _, t)| t == target) {
+                    for c in a..=b {
+                        writeln!(s, "\tcase {}:", c_char(c))?;
                     }
                 }
 
-                write!(&mut res, ") ")?;
+                writeln!(s, "\t\tgoto {};", c_label(target))?;
             }
-            Transition::Empty => panic!(),
+
+            s.push_str("\t}\n");
         }
 
-        Ok(res)
+        writeln!(s, "\tgoto {};", c_label(fallthrough))
     }
 
     pub fn compile(&self) -> Result<String, Error> {
@@ -464,48 +860,33 @@ impl Nfa {
         let mut s = String::new();
 
         let mut accepting_table = String::from("int accepting[] = {");
+        let live = self.live_states();
 
         s.push_str("int matches(char *input) {\n");
 
         s.push_str("\tint state;\n");
-        s.push_str("\tchar c;\n");
+        s.push_str("\tunsigned char c;\n");
         s.push_str("\tint cursor = 0;\n");
         s.push_str("\tStack stack = {};\n");
         s.push_str("\tstack_init(&stack);\n");
-        s.push_str("start:\n");
         s.push_str("\tpush(&stack, -1);\n");
-        write!(&mut s, "\tgoto s{};\n", self.start.index())?;
+        writeln!(&mut s, "\tgoto s{};", self.start.index())?;
 
         for (index, state) in self.graph.node_references() {
-            write!(&mut s, "s{}:\n", index.index())?;
+            writeln!(&mut s, "s{}:", index.index())?;
 
-            write!(&mut s, "\tstate = {};\n", index.index())?;
+            writeln!(&mut s, "\tstate = {};", index.index())?;
             s.push_str("\tif ((c = input[cursor++]) == '\\0') goto end;\n");
 
             if *state == State::Accepting {
-                write!(&mut accepting_table, "{}", "1,")?;
+                accepting_table.push_str("1,");
                 s.push_str("\tclear(&stack);\n");
             } else {
-                write!(&mut accepting_table, "{}", "0,")?; // bit janky
+                accepting_table.push_str("0,"); // bit janky
             }
 
-            write!(&mut s, "\tpush(&stack, {});\n", index.index())?;
-            for neighbor in self.graph.neighbors_directed(index, Direction::Outgoing) {
-                let transition = self
-                    .graph
-                    .edges_connecting(index, neighbor)
-                    .nth(0)
-                    .unwrap()
-                    .weight();
-
-                write!(
-                    &mut s,
-                    "\t{}goto s{};\n",
-                    self.c_condition(&transition)?,
-                    neighbor.index()
-                )?;
-            }
-            s.push_str("\tgoto end;\n");
+            writeln!(&mut s, "\tpush(&stack, {});", index.index())?;
+            self.c_dispatch(&mut s, &self.byte_runs(index, &live))?;
         }
 
         s.push_str("end:\n");
@@ -521,9 +902,277 @@ impl Nfa {
         accepting_table.pop();
         accepting_table.push_str("};\n\n");
 
-        res.extend(accepting_table.chars());
-        res.extend(s.chars());
+        res.push_str(&accepting_table);
+        res.push_str(&s);
 
         Ok(res)
     }
+
+    /// Emits a resumable C matcher fed by chunks. The state is saved in
+    /// `name_state_t` when a chunk runs out and the next call to `name_feed`
+    /// jumps back to its label.
+    pub fn compile_streaming(&self, name: &str) -> Result<String, Error> {
+        let live = self.live_states();
+        let mut res = String::from("#include <stddef.h>\n\n");
+
+        res.push_str("typedef struct {\n");
+        res.push_str("\tint state; /* -1 once no match is possible */\n");
+        res.push_str("\tsize_t offset; /* bytes fed so far */\n");
+        res.push_str("\tlong last_accept; /* length of the longest match, -1 if none */\n");
+        writeln!(&mut res, "}} {name}_state_t;\n")?;
+
+        let accepting: Vec<&str> = self
+            .graph
+            .node_weights()
+            .map(|state| match state {
+                State::Accepting => "1",
+                State::NotAccepting => "0",
+            })
+            .collect();
+
+        writeln!(
+            &mut res,
+            "static const int {name}_accepting[] = {{{}}};\n",
+            accepting.join(", ")
+        )?;
+
+        writeln!(&mut res, "void {name}_init({name}_state_t *st) {{")?;
+        writeln!(&mut res, "\tst->state = {};", self.start.index())?;
+        res.push_str("\tst->offset = 0;\n");
+        writeln!(
+            &mut res,
+            "\tst->last_accept = {name}_accepting[st->state] ? 0 : -1;"
+        )?;
+        res.push_str("}\n\n");
+
+        let mut s = String::new();
+        let mut reads_byte = false;
+
+        for (index, state) in self.graph.node_references() {
+            writeln!(&mut s, "s{}:", index.index())?;
+
+            if *state == State::Accepting {
+                s.push_str("\tst->last_accept = (long)(st->offset + (size_t)(p - start));\n");
+            }
+
+            writeln!(
+                &mut s,
+                "\tif (p == stop) {{ st->state = {}; goto out; }}",
+                index.index()
+            )?;
+
+            // Every byte goes to the same place from a state with a single run
+            let runs = self.byte_runs(index, &live);
+
+            if runs.len() == 1 {
+                s.push_str("\tp++;\n");
+            } else {
+                reads_byte = true;
+                s.push_str("\tc = *p++;\n");
+            }
+
+            self.c_dispatch(&mut s, &runs)?;
+        }
+
+        // Only emitted when some byte has no transition, to avoid an unused label
+        if s.contains("goto end;") {
+            s.push_str("end:\n");
+            s.push_str("\tst->state = -1;\n");
+        }
+
+        s.push_str("out:\n");
+        s.push_str("\tst->offset += (size_t)(p - start);\n");
+        s.push_str("\treturn st->state >= 0;\n");
+        s.push_str("}\n\n");
+
+        writeln!(
+            &mut res,
+            "int {name}_feed({name}_state_t *st, const char *buf, size_t len) {{"
+        )?;
+        res.push_str("\tconst unsigned char *start = (const unsigned char *)buf;\n");
+        res.push_str("\tconst unsigned char *p = start;\n");
+        res.push_str("\tconst unsigned char *stop = start + len;\n");
+
+        if reads_byte {
+            res.push_str("\tunsigned char c;\n");
+        }
+
+        res.push_str("\n\tswitch (st->state) {\n");
+
+        for index in self.graph.node_indices() {
+            writeln!(&mut res, "\tcase {0}: goto s{0};", index.index())?;
+        }
+
+        res.push_str("\tdefault: return 0;\n");
+        res.push_str("\t}\n");
+        res.push_str(&s);
+
+        writeln!(&mut res, "int {name}_finish(const {name}_state_t *st) {{")?;
+        writeln!(
+            &mut res,
+            "\treturn st->state >= 0 && {name}_accepting[st->state];"
+        )?;
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::fmt::Write;
+
+    use crate::{build, determinize, dfa::Dfa, parse, testing};
+
+    #[test]
+    fn test_minimize() {
+        for pattern in testing::PATTERNS {
+            let dfa = determinize(&parse(pattern).unwrap());
+            let minimized = dfa.minimize();
+
+            testing::assert_equivalent(&dfa, &minimized, pattern);
+            assert_eq!(
+                minimized.minimize().graph.node_count(),
+                minimized.graph.node_count(),
+                "{pattern}"
+            );
+        }
+
+        // One state per remainder of the length modulo 97 and 91
+        let minimized = build("(.{97})*&(.{91})*").unwrap();
+        assert_eq!(minimized.graph.node_count(), 97 * 91);
+
+        let minimized = build("(a|b)*a(a|b){3}").unwrap();
+        assert_eq!(minimized.graph.node_count(), 16);
+    }
+
+    #[test]
+    fn test_goto_compiles_cleanly() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        for pattern in [
+            "a",
+            "()",
+            "[]",
+            "(a|b)*abb",
+            "[a-z]+&~(if|else)",
+            "[^a]*\\xff",
+        ] {
+            let code = build(pattern).unwrap().compile().unwrap();
+
+            if let Err(err) = testing::check_c(&code) {
+                panic!("{pattern}:\n{err}\n{code}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_against_dfa() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        let inputs = testing::random_inputs(300);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut harness = String::from("#include <stdio.h>\n");
+        let mut expected = Vec::new();
+
+        harness.push_str(&testing::c_inputs(&inputs));
+
+        // Every input is split into chunks of random lengths, some empty,
+        // ended by -1
+        for (i, input) in inputs.iter().enumerate() {
+            let mut chunks = Vec::new();
+            let mut left = input.len();
+
+            while left > 0 {
+                let len = rng.gen_range(0..=left.min(4));
+                chunks.push(len.to_string());
+                left -= len;
+            }
+
+            chunks.push("-1".to_string());
+            writeln!(
+                harness,
+                "static const long chunks{i}[] = {{{}}};",
+                chunks.join(", ")
+            )
+            .unwrap();
+        }
+
+        let chunks: Vec<String> = (0..inputs.len()).map(|i| format!("chunks{i}")).collect();
+        writeln!(
+            harness,
+            "static const long *const chunks[] = {{{}}};\n",
+            chunks.join(", ")
+        )
+        .unwrap();
+
+        for (i, pattern) in testing::PATTERNS.iter().enumerate() {
+            let nfa = build(pattern).unwrap();
+            let dfa = Dfa::from_nfa(&nfa);
+
+            harness.push_str(&nfa.compile_streaming(&format!("rz{i}")).unwrap());
+            writeln!(harness, "\nstatic void run{i}(void) {{").unwrap();
+            writeln!(harness, "\trz{i}_state_t st;").unwrap();
+            harness.push_str("\tsize_t j, k, offset;\n\n");
+            harness.push_str("\tfor (j = 0; j < sizeof inputs / sizeof *inputs; j++) {\n");
+            writeln!(harness, "\t\trz{i}_init(&st);").unwrap();
+            harness.push_str("\t\tfor (k = 0, offset = 0; chunks[j][k] >= 0; k++) {\n");
+            writeln!(
+                harness,
+                "\t\t\trz{i}_feed(&st, (const char *)inputs[j].bytes + offset, (size_t)chunks[j][k]);"
+            )
+            .unwrap();
+            harness.push_str("\t\t\toffset += (size_t)chunks[j][k];\n");
+            harness.push_str("\t\t}\n");
+            writeln!(
+                harness,
+                "\t\tprintf(\"%d:%ld \", rz{i}_finish(&st), st.last_accept);"
+            )
+            .unwrap();
+            harness.push_str("\t}\n");
+            harness.push_str("\tputchar('\\n');\n");
+            harness.push_str("}\n\n");
+
+            // Whether the whole input matches, and the longest matched prefix
+            let line: String = inputs
+                .iter()
+                .map(|input| {
+                    let longest = (0..=input.len())
+                        .rev()
+                        .find(|&len| dfa.is_match(&input[..len]))
+                        .map_or(-1, |len| len as i64);
+
+                    format!("{}:{longest} ", dfa.is_match(input) as u8)
+                })
+                .collect();
+            expected.push(line);
+        }
+
+        harness.push_str("int main(void) {\n");
+
+        for i in 0..testing::PATTERNS.len() {
+            writeln!(harness, "\trun{i}();").unwrap();
+        }
+
+        harness.push_str("\treturn 0;\n}\n");
+
+        let path = testing::scratch("streaming.c");
+        std::fs::write(&path, harness).unwrap();
+        let output = testing::run_c("streaming", &[&path]);
+        std::fs::remove_file(path).unwrap();
+
+        let output = output.unwrap();
+        assert_eq!(output.lines().count(), expected.len());
+
+        for (pattern, (output, expected)) in
+            testing::PATTERNS.iter().zip(output.lines().zip(&expected))
+        {
+            assert_eq!(output, expected, "{pattern}");
+        }
+    }
 }
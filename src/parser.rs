@@ -1,185 +1,172 @@
-use petgraph::graph::NodeIndex;
+use std::fmt::{Display, Formatter};
 
 use crate::{
-    nfa::{Nfa, Transition},
+    ast::Ast,
+    nfa::{complement_ranges, merge_ranges, ALPHABET_MAX},
     scanner::Token,
 };
 
 // CFG
-// Expr ::= Concat (`|` Concat)* | Concat
-// Concat ::= Duplication*
-// Duplication ::= Grouping`*` | Grouping`+` | Grouping`?` | Grouping`{`(0-9)*`}` | Grouping
-// Grouping ::= `(` Expr `)` | BracketExpr
+// Expr ::= Intersection (`|` Intersection)*
+// Intersection ::= Concat (`&` Concat)*
+// Concat ::= Complement*
+// Complement ::= (`~` | `!`) Complement | Duplication
+// Duplication ::= Grouping`*` | Grouping`+` | Grouping`?` | Grouping`{`(0-9)*`}`
+//     | Grouping`{`(0-9)*`,`(0-9)*`}` | Grouping
+// Grouping ::= `(` Expr `)` | `()` | BracketExpr
 // BracketExpr ::= `[` CharacterClass | `^`CharacterClass `]` | char
 // CharacterClass ::=
 
-fn merge_ranges(mut ranges: Vec<(u8, u8)>) -> Vec<(u8, u8)> {
-    if ranges.is_empty() {
-        return ranges;
-    }
-
-    // Sort ranges by the starting value
-    ranges.sort_by(|a, b| a.0.cmp(&b.0));
-
-    let mut merged_ranges = vec![];
-
-    // Start with the first range
-    let mut current_range = ranges[0].clone();
+/// A syntax error, located by the offset of the character it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub message: String,
+    pub offset: usize,
+}
 
-    for next_range in ranges.into_iter().skip(1) {
-        if next_range.0 <= current_range.1 {
-            // If the next range overlaps or is consecutive, merge it
-            current_range.1 = current_range.1.max(next_range.1);
-        } else {
-            // If the next range does not overlap, push the current range and move to the next
-            merged_ranges.push(current_range);
-            current_range = next_range;
-        }
+impl Error {
+    /// Formats the error followed by the pattern with a caret under the
+    /// offending character.
+    pub fn report(&self, pattern: &str) -> String {
+        format!(
+            "{}\n  {}\n  {}^",
+            self.message,
+            pattern,
+            " ".repeat(self.offset)
+        )
     }
+}
 
-    // Push the last merged range
-    merged_ranges.push(current_range);
-
-    merged_ranges
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
 }
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<(Token, usize)>,
     current: usize,
-    nfa: Nfa,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser {
-            tokens,
-            current: 0,
-            nfa: Nfa::new(),
-        }
+    pub fn new(tokens: Vec<(Token, usize)>) -> Parser {
+        Parser { tokens, current: 0 }
     }
 
-    pub fn parse(mut self) -> Nfa {
-        let (start, end) = self.expr();
-        self.nfa.set_start(start);
-        self.nfa.make_accepting(end);
-        self.nfa
+    pub fn parse(mut self) -> Result<Ast, Error> {
+        let ast = self.expr()?;
+
+        if !self.is_at_end() {
+            return Err(self.error("unmatched parenthesis"));
+        }
+
+        Ok(ast)
     }
 
-    fn expr(&mut self) -> (NodeIndex, NodeIndex) {
-        let mut conn1 = self.concat();
+    fn expr(&mut self) -> Result<Ast, Error> {
+        let mut ast = self.intersection()?;
 
         while self.matches(Token::Union) {
-            let conn2 = self.concat();
+            ast = Ast::Union(Box::new(ast), Box::new(self.intersection()?));
+        }
 
-            let s1 = self.nfa.add_state();
-            let s2 = self.nfa.add_state();
+        Ok(ast)
+    }
 
-            self.nfa.add_e_transition(s1, conn1.0);
-            self.nfa.add_e_transition(s1, conn2.0);
-            self.nfa.add_e_transition(conn1.1, s2);
-            self.nfa.add_e_transition(conn2.1, s2);
+    fn intersection(&mut self) -> Result<Ast, Error> {
+        let mut ast = self.concat()?;
 
-            conn1 = (s1, s2);
+        while self.matches(Token::Intersection) {
+            ast = Ast::Intersection(Box::new(ast), Box::new(self.concat()?));
         }
 
-        conn1
+        Ok(ast)
     }
 
-    pub fn concat(&mut self) -> (NodeIndex, NodeIndex) {
-        let (start, mut end) = self.duplication();
+    fn concat(&mut self) -> Result<Ast, Error> {
+        let mut ast = self.complement()?;
 
-        while !matches!(self.peek(), Token::Eof | Token::RightParen | Token::Union) {
-            let (s1, s2) = self.duplication();
-            self.nfa.add_e_transition(end, s1);
-            end = s2
+        while !matches!(
+            self.peek(),
+            Token::Eof | Token::RightParen | Token::Union | Token::Intersection
+        ) {
+            ast = Ast::Concat(Box::new(ast), Box::new(self.complement()?));
         }
 
-        (start, end)
+        Ok(ast)
     }
 
-    fn duplication(&mut self) -> (NodeIndex, NodeIndex) {
-        let conn = self.primary();
-
-        match self.peek() {
-            Token::Star => {
-                self.advance();
+    fn complement(&mut self) -> Result<Ast, Error> {
+        if self.matches(Token::Tilde) || self.matches(Token::Bang) {
+            return Ok(Ast::Complement(Box::new(self.complement()?)));
+        }
 
-                let s1 = self.nfa.add_state();
-                let s2 = self.nfa.add_state();
+        self.duplication()
+    }
 
-                self.nfa.add_e_transition(s1, conn.0);
-                self.nfa.add_e_transition(conn.1, s2);
-                self.nfa.add_e_transition(conn.1, conn.0);
-                self.nfa.add_e_transition(s1, s2);
+    fn duplication(&mut self) -> Result<Ast, Error> {
+        let ast = self.primary()?;
 
-                return (s1, s2);
+        Ok(match self.peek() {
+            Token::Star => {
+                self.advance();
+                Ast::Star(Box::new(ast))
             }
             Token::QuestionMark => {
                 self.advance();
-
-                let s1 = self.nfa.add_state();
-                let s2 = self.nfa.add_state();
-
-                self.nfa.add_e_transition(s1, s2);
-                self.nfa.add_e_transition(s1, conn.0);
-                self.nfa.add_e_transition(conn.1, s2);
-
-                return (s1, s2);
+                Ast::Optional(Box::new(ast))
             }
             Token::Plus => {
                 self.advance();
-
-                let conn2 = self.nfa.clone_subgraph(conn.0, conn.1);
-
-                let s1 = self.nfa.add_state();
-                let s2 = self.nfa.add_state();
-
-                self.nfa.add_e_transition(conn.1, s1);
-                self.nfa.add_e_transition(s1, conn2.0);
-                self.nfa.add_e_transition(conn2.1, s2);
-                self.nfa.add_e_transition(conn2.1, conn2.0);
-                self.nfa.add_e_transition(s1, s2);
-
-                return (conn.0, s2);
+                Ast::Plus(Box::new(ast))
+            }
+            Token::Repeat(0) => {
+                self.advance();
+                Ast::Empty
             }
             Token::Repeat(n) => {
                 self.advance();
-
-                let mut connector = conn.1;
-
-                for _ in 0..n - 1 {
-                    let new_conn = self.nfa.clone_subgraph(conn.0, conn.1);
-
-                    self.nfa.add_e_transition(connector, new_conn.0);
-                    connector = new_conn.1;
-                }
-
-                return (conn.0, connector);
+                Ast::Repeat(Box::new(ast), n)
             }
-            Token::RepeatRange(Some(a), Some(b)) => {
-                todo!()
+            // a{n,} is a{n}a*, and a{n,m} is a{n}(a?){m-n}
+            Token::RepeatRange(n, None) => {
+                self.advance();
+                let rest = Ast::Star(Box::new(ast.clone()));
+                concat(repeat(ast, n.unwrap_or(0)), rest)
             }
-
-            _ => {}
-        };
-
-        conn
+            Token::RepeatRange(n, Some(m)) if n.unwrap_or(0) > m => {
+                return Err(self.error("invalid repetition range"));
+            }
+            Token::RepeatRange(n, Some(m)) => {
+                self.advance();
+                let n = n.unwrap_or(0);
+                let rest = repeat(Ast::Optional(Box::new(ast.clone())), m - n);
+                concat(repeat(ast, n), rest)
+            }
+            _ => ast,
+        })
     }
 
-    fn primary(&mut self) -> (NodeIndex, NodeIndex) {
+    fn primary(&mut self) -> Result<Ast, Error> {
         match self.peek() {
             Token::LeftParen => {
+                let open = self.current;
                 self.advance();
-                let conn = self.expr();
 
                 if self.matches(Token::RightParen) {
-                    conn
+                    return Ok(Ast::Empty);
+                }
+
+                let ast = self.expr()?;
+
+                if self.matches(Token::RightParen) {
+                    Ok(ast)
                 } else {
-                    self.peek();
-                    panic!("Unbalanced paren")
+                    Err(self.error_at(open, "unclosed parenthesis"))
                 }
             }
             Token::LeftBracket => {
+                let open = self.current;
                 self.advance();
 
                 let mut inclusive = true;
@@ -191,98 +178,109 @@ impl Parser {
                 let mut ranges: Vec<(u8, u8)> = Vec::new();
 
                 while !self.matches(Token::RightBracket) {
-                    let range = self.character_class();
+                    if self.is_at_end() {
+                        return Err(self.error_at(open, "unclosed bracket"));
+                    }
+
+                    let range = self.character_class()?;
                     ranges.push(range);
                 }
 
-                assert!(inclusive, "Not implemented");
-
-                let mut ranges = merge_ranges(ranges);
-
-                let mut conn1 = {
-                    let first_range = ranges.pop().unwrap();
-
-                    let s1 = self.nfa.add_state();
-                    let s2 = self.nfa.add_state();
-
-                    self.nfa.add_transition(
-                        s1,
-                        s2,
-                        Transition::Range(first_range.0, first_range.1),
-                    );
-
-                    (s1, s2)
-                };
-
-                for range in ranges {
-                    let conn2 = {
-                        let s1 = self.nfa.add_state();
-                        let s2 = self.nfa.add_state();
-
-                        self.nfa
-                            .add_transition(s1, s2, Transition::Range(range.0, range.1));
-
-                        (s1, s2)
-                    };
-
-                    let s1 = self.nfa.add_state();
-                    let s2 = self.nfa.add_state();
+                let ranges = merge_ranges(ranges);
 
-                    self.nfa.add_e_transition(s1, conn1.0);
-                    self.nfa.add_e_transition(s1, conn2.0);
-                    self.nfa.add_e_transition(conn1.1, s2);
-                    self.nfa.add_e_transition(conn2.1, s2);
-
-                    conn1 = (s1, s2);
+                if inclusive {
+                    Ok(Ast::Class(ranges))
+                } else {
+                    Ok(Ast::Class(complement_ranges(&ranges)))
                 }
-
-                conn1
             }
             Token::Char(c) => {
+                let c = self.byte(c)?;
                 self.advance();
-
-                let s1 = self.nfa.add_state();
-                let s2 = self.nfa.add_state();
-                self.nfa
-                    .add_transition(s1, s2, Transition::Range(c as u8, c as u8));
-                (s1, s2)
+                Ok(Ast::Class(vec![(c, c)]))
             }
             Token::Dot => {
                 self.advance();
-
-                let s1 = self.nfa.add_state();
-                let s2 = self.nfa.add_state();
-                self.nfa
-                    .add_transition(s1, s2, Transition::Range(0, 127));
-                (s1, s2)
+                Ok(Ast::Class(vec![(0, ALPHABET_MAX)]))
             }
-            _ => panic!("Invalid expression: {:?}", self.peek()),
+            Token::Star
+            | Token::Plus
+            | Token::QuestionMark
+            | Token::Repeat(_)
+            | Token::RepeatRange(_, _) => Err(self.error("nothing to repeat")),
+            Token::Error => Err(self.error("invalid repetition")),
+            Token::Eof => Err(self.error("expected an expression")),
+            _ => Err(self.error("unexpected character")),
         }
     }
 
-    fn character_class(&mut self) -> (u8, u8) {
+    fn character_class(&mut self) -> Result<(u8, u8), Error> {
+        let first = self.current;
+
         match self.advance() {
             Token::Char(c1) => {
-                if self.matches(Token::Hyphen) {
-                    match self.advance() {
-                        Token::Char(c2) => (c1 as u8, c2 as u8),
-                        Token::Dot => ('.' as u8, '.' as u8),
-                        Token::QuestionMark => ('?' as u8, '?' as u8),
-                        Token::Plus => ('+' as u8, '+' as u8),
-                        Token::Star => ('*' as u8, '*' as u8),
-                        Token::Union => ('|' as u8, '|' as u8),
-                        t => panic!("Not implemented: {t:?}"),
+                let c1 = self.byte_at(first, c1)?;
+
+                if !self.matches(Token::Hyphen) {
+                    return Ok((c1, c1));
+                }
+
+                let last = self.current;
+
+                match self.advance() {
+                    Token::Char(c2) => {
+                        let c2 = self.byte_at(last, c2)?;
+
+                        if c2 < c1 {
+                            return Err(self.error_at(first, "range out of order"));
+                        }
+
+                        Ok((c1, c2))
                     }
-                } else {
-                    (c1 as u8, c1 as u8)
+                    t => match Self::literal(t) {
+                        Some(c) => Ok((c, c)),
+                        None => Err(self.error_at(last, "incomplete range")),
+                    },
                 }
             }
-            Token::Dot => ('.' as u8, '.' as u8),
-            Token::QuestionMark => ('?' as u8, '?' as u8),
-            Token::Plus => ('+' as u8, '+' as u8),
-            Token::Star => ('*' as u8, '*' as u8),
-            Token::Union => ('|' as u8, '|' as u8),
-            t => panic!("Not implemented"),
+            t => match Self::literal(t) {
+                Some(c) => Ok((c, c)),
+                None => Err(self.error_at(first, "unexpected character in bracket expression")),
+            },
+        }
+    }
+
+    /// Operators that stand for themselves inside a bracket expression.
+    fn literal(token: Token) -> Option<u8> {
+        match token {
+            Token::Dot => Some(b'.'),
+            Token::QuestionMark => Some(b'?'),
+            Token::Plus => Some(b'+'),
+            Token::Star => Some(b'*'),
+            Token::Union => Some(b'|'),
+            Token::Intersection => Some(b'&'),
+            Token::Tilde => Some(b'~'),
+            Token::Bang => Some(b'!'),
+            _ => None,
+        }
+    }
+
+    fn byte(&self, c: char) -> Result<u8, Error> {
+        self.byte_at(self.current, c)
+    }
+
+    fn byte_at(&self, index: usize, c: char) -> Result<u8, Error> {
+        u8::try_from(c).map_err(|_| self.error_at(index, "character does not fit in a byte"))
+    }
+
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.current, message)
+    }
+
+    fn error_at(&self, index: usize, message: &str) -> Error {
+        Error {
+            message: message.to_string(),
+            offset: self.tokens[index].1,
         }
     }
 
@@ -304,14 +302,52 @@ impl Parser {
     }
 
     fn peek(&self) -> Token {
-        self.tokens[self.current]
+        self.tokens[self.current].0
     }
 
     fn previous(&self) -> Token {
-        self.tokens[self.current - 1]
+        self.tokens[self.current - 1].0
     }
 
     fn is_at_end(&self) -> bool {
         self.peek() == Token::Eof
     }
 }
+
+fn repeat(ast: Ast, n: u64) -> Ast {
+    match n {
+        0 => Ast::Empty,
+        n => Ast::Repeat(Box::new(ast), n),
+    }
+}
+
+fn concat(a: Ast, b: Ast) -> Ast {
+    match (a, b) {
+        (Ast::Empty, b) => b,
+        (a, Ast::Empty) => a,
+        (a, b) => Ast::Concat(Box::new(a), Box::new(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{build, parse, testing::assert_equivalent};
+
+    #[test]
+    fn test_repeat_ranges() {
+        for (range, expanded) in [
+            ("a{2,4}", "aaa?a?"),
+            ("a{,2}", "a?a?"),
+            ("a{2,}", "aaa*"),
+            ("a{0,}", "a*"),
+            ("a{3,3}", "aaa"),
+            ("(ab){0,0}c", "c"),
+        ] {
+            let dfas = (build(range).unwrap(), build(expanded).unwrap());
+            assert_equivalent(&dfas.0, &dfas.1, range);
+        }
+
+        assert_eq!(parse("a{3,1}").unwrap_err().offset, 1);
+        assert_eq!(parse("{1,2}").unwrap_err().message, "nothing to repeat");
+    }
+}
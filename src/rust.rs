@@ -0,0 +1,144 @@
+use std::fmt::{Error, Write};
+
+use petgraph::{visit::EdgeRef, Direction};
+
+use crate::{
+    escape::rust_byte,
+    nfa::{Nfa, State},
+};
+
+fn byte_pattern(ranges: &[(u8, u8)]) -> String {
+    let patterns: Vec<String> = ranges
+        .iter()
+        .map(|&(a, b)| match (a, b) {
+            (0, 255) => "_".to_string(),
+            (a, b) if a == b => rust_byte(a),
+            (a, b) => format!("{}..={}", rust_byte(a), rust_byte(b)),
+        })
+        .collect();
+
+    patterns.join(" | ")
+}
+
+/// Pattern matching the sorted `states`, runs of consecutive states being
+/// written as ranges as clippy asks.
+fn state_pattern(states: &[usize]) -> String {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+
+    for &state in states {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == state => *last = state,
+            _ => runs.push((state, state)),
+        }
+    }
+
+    let patterns: Vec<String> = runs
+        .iter()
+        .map(|&(a, b)| match a == b {
+            true => a.to_string(),
+            false => format!("{a}..={b}"),
+        })
+        .collect();
+
+    patterns.join(" | ")
+}
+
+impl Nfa {
+    /// Emits a Rust module exporting `is_match`, which tells whether the whole
+    /// input is matched. Expects the output of `minimize`; the generated code
+    /// only uses `core`, so it can be included in `#![no_std]` crates.
+    pub fn compile_rust(&self) -> Result<String, Error> {
+        let mut res = String::from("// Generated by regez, do not edit.\n\n");
+
+        let accepting: Vec<usize> = self
+            .graph
+            .node_indices()
+            .filter(|&node| self.graph[node] == State::Accepting)
+            .map(|node| node.index())
+            .collect();
+
+        if accepting.is_empty() {
+            res.push_str("pub fn is_match(_input: &[u8]) -> bool {\n");
+            res.push_str("    false\n");
+            res.push_str("}\n");
+            return Ok(res);
+        }
+
+        // Without any transition only the empty string can be matched, and a
+        // loop that always returns would not pass clippy
+        if self.graph.edge_count() == 0 {
+            res.push_str("pub fn is_match(input: &[u8]) -> bool {\n");
+            res.push_str("    input.is_empty()\n");
+            res.push_str("}\n");
+            return Ok(res);
+        }
+
+        res.push_str("pub fn is_match(input: &[u8]) -> bool {\n");
+        writeln!(&mut res, "    let mut state = {};", self.start.index())?;
+        res.push('\n');
+        res.push_str("    for &c in input {\n");
+        res.push_str("        state = match (state, c) {\n");
+
+        for node in self.graph.node_indices() {
+            for edge in self.graph.edges_directed(node, Direction::Outgoing) {
+                writeln!(
+                    &mut res,
+                    "            ({}, {}) => {},",
+                    node.index(),
+                    byte_pattern(&edge.weight().ranges()),
+                    edge.target().index()
+                )?;
+            }
+        }
+
+        res.push_str("            _ => return false,\n");
+        res.push_str("        };\n");
+        res.push_str("    }\n");
+        res.push('\n');
+        writeln!(
+            &mut res,
+            "    matches!(state, {})",
+            state_pattern(&accepting)
+        )?;
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::{build, testing};
+
+    #[test]
+    fn test_clippy_clean() {
+        let linter = match testing::installed("clippy-driver") {
+            true => "clippy-driver",
+            false => "rustc",
+        };
+
+        for (i, pattern) in testing::PATTERNS.iter().enumerate() {
+            let path = testing::scratch(&format!("rust-{i}.rs"));
+            let metadata = testing::scratch(&format!("rust-{i}.rmeta"));
+            std::fs::write(&path, build(pattern).unwrap().compile_rust().unwrap()).unwrap();
+
+            let output = testing::run(
+                Command::new(linter)
+                    .args(["--edition", "2021", "--crate-type", "lib", "-D", "warnings"])
+                    .arg("--emit=metadata")
+                    .arg("-o")
+                    .arg(&metadata)
+                    .arg(&path),
+            );
+
+            let _ = std::fs::remove_file(metadata);
+            std::fs::remove_file(path).unwrap();
+
+            if let Err(err) = output {
+                panic!("{pattern}:\n{err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use num_bigint::{BigUint, RandBigInt};
+use rand::Rng;
+use std::ops::RangeInclusive;
+
+use crate::dfa::Dfa;
+
+/// Draws accepted strings of a given length uniformly at random, weighting
+/// every transition by the number of accepted suffixes behind it.
+pub struct Sampler<'a> {
+    dfa: &'a Dfa,
+    class_sizes: Vec<usize>,
+    // counts[k][s] is the number of strings of length k accepted from state s
+    counts: Vec<Vec<BigUint>>,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn new(dfa: &'a Dfa, max_len: usize) -> Sampler<'a> {
+        let mut class_sizes = vec![0; dfa.class_count];
+
+        for &class in dfa.class_map.iter() {
+            class_sizes[class as usize] += 1;
+        }
+
+        let mut counts = vec![dfa
+            .accepting
+            .iter()
+            .map(|&accepting| BigUint::from(accepting as u8))
+            .collect::<Vec<_>>()];
+
+        for len in 1..=max_len {
+            let row = (0..dfa.state_count())
+                .map(|state| {
+                    class_sizes
+                        .iter()
+                        .enumerate()
+                        .map(|(class, &size)| {
+                            let next = dfa.transitions[state * dfa.class_count + class];
+                            &counts[len - 1][next] * size
+                        })
+                        .sum()
+                })
+                .collect();
+
+            counts.push(row);
+        }
+
+        Sampler {
+            dfa,
+            class_sizes,
+            counts,
+        }
+    }
+
+    pub fn count(&self, len: usize) -> &BigUint {
+        &self.counts[len][self.dfa.start]
+    }
+
+    /// Lengths of `range` holding at least one accepted string.
+    pub fn lengths(&self, range: RangeInclusive<usize>) -> Vec<usize> {
+        range
+            .filter(|&len| *self.count(len) != BigUint::ZERO)
+            .collect()
+    }
+
+    pub fn sample<R: Rng>(&self, len: usize, rng: &mut R) -> Option<Vec<u8>> {
+        let mut state = self.dfa.start;
+        let mut res = Vec::with_capacity(len);
+
+        if self.counts[len][state] == BigUint::ZERO {
+            return None;
+        }
+
+        for remaining in (0..len).rev() {
+            let mut r = rng.gen_biguint_below(&self.counts[remaining + 1][state]);
+
+            for (class, &size) in self.class_sizes.iter().enumerate() {
+                let next = self.dfa.transitions[state * self.dfa.class_count + class];
+                let suffixes = &self.counts[remaining][next];
+                let weight = suffixes * size;
+
+                if r < weight {
+                    // r is uniform below size * suffixes, so r / suffixes
+                    // picks a byte of the class uniformly
+                    let index: usize = (r / suffixes).try_into().unwrap();
+                    let c = (0..=255u8)
+                        .filter(|&c| self.dfa.class_map[c as usize] as usize == class)
+                        .nth(index)
+                        .unwrap();
+
+                    res.push(c);
+                    state = next;
+                    break;
+                }
+
+                r -= weight;
+            }
+        }
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rustc_hash::FxHashMap;
+
+    use super::Sampler;
+    use crate::{build, dfa::Dfa};
+
+    #[test]
+    fn test_lengths() {
+        let dfa = Dfa::from_nfa(&build("a{2}(bc)*").unwrap());
+        let sampler = Sampler::new(&dfa, 9);
+
+        assert_eq!(sampler.lengths(0..=9), [2, 4, 6, 8]);
+        assert_eq!(sampler.lengths(3..=5), [4]);
+        assert!(sampler.lengths(9..=9).is_empty());
+        assert_eq!(*sampler.count(4), BigUint::from(1u8));
+
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for len in 0..=9 {
+            match sampler.sample(len, &mut rng) {
+                Some(string) => {
+                    assert_eq!(string.len(), len);
+                    assert!(dfa.is_match(&string));
+                }
+                None => assert!(!sampler.lengths(0..=9).contains(&len)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_uniform() {
+        // 9 + 8 strings, 6 of them matched both ways, which must not make them
+        // more likely
+        let dfa = Dfa::from_nfa(&build("[abc][xyz]|[ab][w-z]").unwrap());
+        let sampler = Sampler::new(&dfa, 2);
+        assert_eq!(*sampler.count(2), BigUint::from(11u8));
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts: FxHashMap<Vec<u8>, usize> = FxHashMap::default();
+
+        for _ in 0..11_000 {
+            *counts
+                .entry(sampler.sample(2, &mut rng).unwrap())
+                .or_default() += 1;
+        }
+
+        assert_eq!(counts.len(), 11);
+
+        for (string, count) in counts {
+            assert!(dfa.is_match(&string));
+            assert!(
+                (850..1150).contains(&count),
+                "{string:?} drawn {count} times"
+            );
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let dfa = Dfa::from_nfa(&build("[a-z]+@[a-z]+").unwrap());
+        let sampler = Sampler::new(&dfa, 20);
+        let draw = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..10)
+                .map(|_| sampler.sample(20, &mut rng).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(draw(7), draw(7));
+        assert_ne!(draw(7), draw(8));
+    }
+}
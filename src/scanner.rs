@@ -10,6 +10,9 @@ pub enum Token {
     Carret,
     Star,
     Union,
+    Intersection,
+    Tilde,
+    Bang,
     Dot,
     QuestionMark,
     Plus,
@@ -24,18 +27,23 @@ pub struct Scanner<'a> {
 }
 
 impl<'a> Scanner<'a> {
-    pub fn new(input: &'a [char]) -> Scanner {
+    pub fn new(input: &'a [char]) -> Scanner<'a> {
         Scanner { input, current: 0 }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    /// Returns the tokens along with the offset of the character they start at.
+    pub fn scan_tokens(&mut self) -> Vec<(Token, usize)> {
         let mut res = Vec::new();
 
         while !self.is_at_end() {
+            let start = self.current;
             let c = self.advance();
 
-            res.push(match c {
+            let token = match c {
                 '|' => Token::Union,
+                '&' => Token::Intersection,
+                '~' => Token::Tilde,
+                '!' => Token::Bang,
                 '(' => Token::LeftParen,
                 ')' => Token::RightParen,
                 '[' => Token::LeftBracket,
@@ -47,15 +55,34 @@ impl<'a> Scanner<'a> {
                 '+' => Token::Plus,
                 '-' => Token::Hyphen,
                 '{' => self.repeat(),
+                '\\' if !self.is_at_end() => self.escape(),
                 c => Token::Char(c),
-            });
+            };
+
+            res.push((token, start));
         }
 
-        res.push(Token::Eof);
+        res.push((Token::Eof, self.current));
 
         res
     }
 
+    /// `\xNN` stands for the byte NN, any other escaped character for itself.
+    fn escape(&mut self) -> Token {
+        let c = self.advance();
+
+        if c == 'x' && self.current + 2 <= self.input.len() {
+            let digits: String = self.input[self.current..self.current + 2].iter().collect();
+
+            if let Ok(byte) = u8::from_str_radix(&digits, 16) {
+                self.current += 2;
+                return Token::Char(byte as char);
+            }
+        }
+
+        Token::Char(c)
+    }
+
     fn repeat(&mut self) -> Token {
         let mut first = 0;
         let mut second = 0;
@@ -126,14 +153,6 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn peek_next(&self) -> char {
-        if self.current + 1 >= self.input.len() {
-            '\0'
-        } else {
-            self.input[self.current + 1]
-        }
-    }
-
     fn is_at_end(&self) -> bool {
         self.current == self.input.len()
     }
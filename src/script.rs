@@ -0,0 +1,258 @@
+use std::fmt::{Error, Write};
+
+use crate::{dfa::Dfa, table::int_bits};
+
+impl Dfa {
+    /// Emits an ES module exporting `isMatch`, which takes a string, encoded
+    /// as UTF-8, or a `Uint8Array` and tells whether the whole input is
+    /// matched. With `typescript` the module is annotated with types.
+    pub fn compile_js(&self, typescript: bool) -> Result<String, Error> {
+        let array = format!("Uint{}Array", int_bits(self.state_count()));
+        let mut res = String::from("// Generated by regez, do not edit.\n\n");
+
+        res.push_str("const classes = new Uint8Array([\n");
+
+        for row in self.class_rows() {
+            writeln!(&mut res, "  {row},")?;
+        }
+
+        res.push_str("]);\n\n");
+
+        writeln!(
+            &mut res,
+            "// Next state at index state * {} + class",
+            self.class_count
+        )?;
+        writeln!(&mut res, "const transitions = new {array}([")?;
+
+        for row in self.transition_rows() {
+            writeln!(&mut res, "  {row},")?;
+        }
+
+        res.push_str("]);\n\n");
+
+        writeln!(
+            &mut res,
+            "const accepting = new Uint8Array([{}]);\n",
+            self.accepting_list()
+        )?;
+
+        if typescript {
+            res.push_str("export function isMatch(input: string | Uint8Array): boolean {\n");
+        } else {
+            res.push_str("export function isMatch(input) {\n");
+        }
+
+        res.push_str("  const bytes = typeof input === \"string\" ? new TextEncoder().encode(input) : input;\n");
+        writeln!(&mut res, "  let state = {};\n", self.start)?;
+        res.push_str("  for (const c of bytes) {\n");
+        writeln!(
+            &mut res,
+            "    state = transitions[state * {} + classes[c]];",
+            self.class_count
+        )?;
+        res.push_str("  }\n\n");
+        res.push_str("  return accepting[state] === 1;\n");
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+
+    /// Emits a Python module defining `is_match`, which takes `bytes` or a
+    /// `str`, encoded as UTF-8, and tells whether the whole input is matched.
+    pub fn compile_python(&self) -> Result<String, Error> {
+        let mut res = String::from("# Generated by regez, do not edit.\n\n");
+
+        res.push_str("CLASSES = [\n");
+
+        for row in self.class_rows() {
+            writeln!(&mut res, "    {row},")?;
+        }
+
+        res.push_str("]\n\n");
+        res.push_str("TRANSITIONS = [\n");
+
+        for row in self.transition_rows() {
+            writeln!(&mut res, "    [{row}],")?;
+        }
+
+        res.push_str("]\n\n");
+        writeln!(&mut res, "ACCEPTING = [{}]\n\n", self.accepting_list())?;
+
+        res.push_str("def is_match(data):\n");
+        res.push_str("    if isinstance(data, str):\n");
+        res.push_str("        data = data.encode()\n\n");
+        writeln!(&mut res, "    state = {}\n", self.start)?;
+        res.push_str("    for c in data:\n");
+        res.push_str("        state = TRANSITIONS[state][CLASSES[c]]\n\n");
+        res.push_str("    return ACCEPTING[state] == 1\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::{build, dfa::Dfa, testing};
+
+    /// Inputs as literals of arrays of numbers, valid in both languages.
+    fn array_literals(inputs: &[Vec<u8>]) -> String {
+        let arrays: Vec<String> = inputs
+            .iter()
+            .map(|input| {
+                let bytes: Vec<String> = input.iter().map(u8::to_string).collect();
+                format!("[{}]", bytes.join(", "))
+            })
+            .collect();
+
+        format!("[{}]", arrays.join(", "))
+    }
+
+    /// Writes `modules` and `driver` to a scratch directory and runs the
+    /// driver with `program`, which prints a line of verdicts per module.
+    fn run_modules(
+        program: &str,
+        modules: &[String],
+        driver: &str,
+        extension: &str,
+    ) -> Vec<String> {
+        let dir = testing::scratch(extension);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (i, module) in modules.iter().enumerate() {
+            std::fs::write(dir.join(format!("m{i}.{extension}")), module).unwrap();
+        }
+
+        std::fs::write(dir.join(format!("driver.{extension}")), driver).unwrap();
+
+        let output = testing::run(
+            Command::new(program)
+                .arg(format!("driver.{extension}"))
+                .current_dir(&dir),
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let lines: Vec<String> = output.unwrap().lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), modules.len());
+
+        lines
+    }
+
+    const STRINGS: &[&str] = &["", "abb", "x@y.org", "é", "ab\u{0}"];
+
+    fn expected(dfa: &Dfa, inputs: &[Vec<u8>]) -> String {
+        let strings: Vec<Vec<u8>> = STRINGS.iter().map(|s| s.as_bytes().to_vec()).collect();
+        testing::verdicts(dfa, inputs) + &testing::verdicts(dfa, &strings)
+    }
+
+    fn dfas() -> Vec<Dfa> {
+        testing::PATTERNS
+            .iter()
+            .map(|pattern| Dfa::from_nfa(&build(pattern).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_js_against_dfa() {
+        if !testing::installed("node") {
+            return;
+        }
+
+        let inputs = testing::random_inputs(400);
+        let dfas = dfas();
+        let modules: Vec<String> = dfas
+            .iter()
+            .map(|dfa| dfa.compile_js(false).unwrap())
+            .collect();
+
+        let mut driver = String::new();
+
+        for i in 0..modules.len() {
+            driver.push_str(&format!(
+                "import {{ isMatch as m{i} }} from \"./m{i}.mjs\";\n"
+            ));
+        }
+
+        let matchers: Vec<String> = (0..modules.len()).map(|i| format!("m{i}")).collect();
+        driver.push_str(&format!(
+            "\nconst inputs = {}.map((bytes) => new Uint8Array(bytes));\n",
+            array_literals(&inputs)
+        ));
+        driver.push_str(&format!("const strings = {STRINGS:?};\n\n"));
+        driver.push_str(&format!(
+            "for (const isMatch of [{}]) {{\n",
+            matchers.join(", ")
+        ));
+        driver.push_str("  const verdicts = [...inputs, ...strings].map((input) => (isMatch(input) ? 1 : 0));\n");
+        driver.push_str("  console.log(verdicts.join(\"\"));\n");
+        driver.push_str("}\n");
+
+        let outputs = run_modules("node", &modules, &driver, "mjs");
+
+        for ((pattern, dfa), output) in testing::PATTERNS.iter().zip(&dfas).zip(outputs) {
+            assert_eq!(output, expected(dfa, &inputs), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_python_against_dfa() {
+        if !testing::installed("python3") {
+            return;
+        }
+
+        let inputs = testing::random_inputs(400);
+        let dfas = dfas();
+        let modules: Vec<String> = dfas
+            .iter()
+            .map(|dfa| dfa.compile_python().unwrap())
+            .collect();
+
+        let mut driver = String::new();
+
+        for i in 0..modules.len() {
+            driver.push_str(&format!("import m{i}\n"));
+        }
+
+        let matchers: Vec<String> = (0..modules.len())
+            .map(|i| format!("m{i}.is_match"))
+            .collect();
+        driver.push_str(&format!(
+            "\ninputs = [bytes(b) for b in {}]\n",
+            array_literals(&inputs)
+        ));
+        driver.push_str(&format!("strings = {STRINGS:?}\n\n"));
+        driver.push_str(&format!("for is_match in [{}]:\n", matchers.join(", ")));
+        driver.push_str(
+            "    print(\"\".join(\"1\" if is_match(x) else \"0\" for x in inputs + strings))\n",
+        );
+
+        let outputs = run_modules("python3", &modules, &driver, "py");
+
+        for ((pattern, dfa), output) in testing::PATTERNS.iter().zip(&dfas).zip(outputs) {
+            assert_eq!(output, expected(dfa, &inputs), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_ts_annotates_js() {
+        for dfa in dfas() {
+            let (js, ts) = (
+                dfa.compile_js(false).unwrap(),
+                dfa.compile_js(true).unwrap(),
+            );
+            let differing: Vec<(&str, &str)> =
+                js.lines().zip(ts.lines()).filter(|(a, b)| a != b).collect();
+
+            assert_eq!(js.lines().count(), ts.lines().count());
+            assert_eq!(
+                differing,
+                [(
+                    "export function isMatch(input) {",
+                    "export function isMatch(input: string | Uint8Array): boolean {"
+                )]
+            );
+        }
+    }
+}
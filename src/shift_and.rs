@@ -0,0 +1,146 @@
+use std::fmt::{Error, Write};
+
+use crate::glushkov::Glushkov;
+
+/// Most positions the state vector holds, bit 0 standing for the start.
+pub const SHIFT_AND_MAX_POSITIONS: usize = 63;
+
+fn write_masks(res: &mut String, masks: &[u64]) -> Result<(), Error> {
+    for row in masks.chunks(4) {
+        let row: Vec<String> = row.iter().map(|mask| format!("0x{mask:x}")).collect();
+        writeln!(res, "\t{},", row.join(", "))?;
+    }
+
+    Ok(())
+}
+
+impl Glushkov {
+    pub fn fits_shift_and(&self) -> bool {
+        self.position_count() <= SHIFT_AND_MAX_POSITIONS
+    }
+
+    /// Emits C code simulating the position automaton with one bit per
+    /// position, as an alternative to determinizing it. A step looks up the
+    /// positions following the active ones a byte of the state at a time,
+    /// then keeps those matching the input byte. The whole input has to
+    /// match. Expects `fits_shift_and`.
+    pub fn compile_shift_and(&self) -> Result<String, Error> {
+        let bit = |position: usize| 1u64 << (position + 1);
+        let chunks = (self.position_count() + 1).div_ceil(8);
+        let mut res = String::from("#include <stdint.h>\n\n");
+
+        let mut masks = [0u64; 256];
+
+        for (position, ranges) in self.classes.iter().enumerate() {
+            for &(a, b) in ranges {
+                for c in a..=b {
+                    masks[c as usize] |= bit(position);
+                }
+            }
+        }
+
+        res.push_str("/* Positions matching each byte */\n");
+        res.push_str("static const uint64_t masks[256] = {\n");
+        write_masks(&mut res, &masks)?;
+        res.push_str("};\n\n");
+
+        // Bit 0 is followed by the first positions
+        let follow = |i: usize| -> u64 {
+            let next = if i == 0 {
+                &self.first
+            } else {
+                &self.follow[i - 1]
+            };
+
+            next.iter().fold(0, |mask, &position| mask | bit(position))
+        };
+
+        res.push_str("/* Positions following the set bits of each byte of the state */\n");
+        writeln!(&mut res, "static const uint64_t follow[{chunks}][256] = {{")?;
+
+        for chunk in 0..chunks {
+            let row: Vec<u64> = (0..256)
+                .map(|byte: usize| {
+                    (0..8)
+                        .filter(|b| byte & 1 << b != 0)
+                        .map(|b| 8 * chunk + b)
+                        .filter(|&i| i <= self.position_count())
+                        .fold(0, |mask, i| mask | follow(i))
+                })
+                .collect();
+
+            res.push_str("\t{\n");
+            write_masks(&mut res, &row)?;
+            res.push_str("\t},\n");
+        }
+
+        res.push_str("};\n\n");
+
+        let last = self
+            .last
+            .iter()
+            .fold(self.nullable as u64, |mask, &position| mask | bit(position));
+
+        res.push_str("int matches(char *input) {\n");
+        res.push_str("\tuint64_t state = 1;\n");
+        res.push_str("\tunsigned char c;\n\n");
+        res.push_str("\twhile ((c = *input++) != '\\0') {\n");
+
+        let lookups: Vec<String> = (0..chunks)
+            .map(|chunk| match chunk {
+                0 => "follow[0][state & 0xff]".to_string(),
+                _ => format!("follow[{chunk}][(state >> {}) & 0xff]", 8 * chunk),
+            })
+            .collect();
+
+        writeln!(
+            &mut res,
+            "\t\tstate = ({}) & masks[c];",
+            lookups.join(" | ")
+        )?;
+        res.push_str("\t\tif (state == 0)\n");
+        res.push_str("\t\t\treturn 0;\n");
+        res.push_str("\t}\n\n");
+        writeln!(&mut res, "\treturn (state & 0x{last:x}) != 0;")?;
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SHIFT_AND_MAX_POSITIONS;
+    use crate::{build, dfa::Dfa, glushkov::Glushkov, parse, testing};
+
+    #[test]
+    fn test_shift_and_against_dfa() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        let inputs: Vec<Vec<u8>> = testing::random_inputs(400)
+            .into_iter()
+            .filter(|input| !input.contains(&0))
+            .collect();
+        let mut patterns = Vec::new();
+        let mut sources = Vec::new();
+
+        // Patterns with & or ~ have no position automaton
+        for pattern in testing::PATTERNS {
+            let ast = parse(pattern).unwrap();
+
+            if let Some(glushkov) = Glushkov::new_within(&ast, SHIFT_AND_MAX_POSITIONS) {
+                patterns.push(pattern);
+                sources.push(glushkov.compile_shift_and().unwrap());
+            }
+        }
+
+        let outputs = testing::run_c_matches("shift-and", &sources, &inputs);
+
+        for (pattern, output) in patterns.into_iter().zip(outputs) {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+            assert_eq!(output, testing::verdicts(&dfa, &inputs), "{pattern}");
+        }
+    }
+}
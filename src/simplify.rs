@@ -0,0 +1,213 @@
+use petgraph::{graph::NodeIndex, visit::EdgeRef};
+use rustc_hash::FxHashMap;
+
+use crate::nfa::{merge_ranges, Nfa, State, Transition};
+
+/// Whether a state accepts, and the bytes leading to each class of targets.
+type Signature = (bool, Vec<(usize, Vec<(u8, u8)>)>);
+
+impl Nfa {
+    /// Shrinks the NFA before determinization: removes epsilon transitions,
+    /// drops unreachable and dead states, then merges equivalent states.
+    pub fn simplify(&self) -> Nfa {
+        self.remove_epsilons().trim().merge_equivalent_states()
+    }
+
+    /// Equivalent NFA without epsilon transitions. Every state takes the
+    /// byte transitions of the states in its epsilon closure, and accepts if
+    /// one of them does. Only the start and the targets of byte transitions
+    /// are kept, the rest being reachable through epsilon transitions alone.
+    pub fn remove_epsilons(&self) -> Nfa {
+        let e_closure = self.e_closure();
+        let mut res = Nfa::new();
+        let mut mapping: FxHashMap<NodeIndex, NodeIndex> = FxHashMap::default();
+        let mut stack = vec![self.start];
+
+        mapping.insert(self.start, res.add_state());
+        res.set_start(mapping[&self.start]);
+
+        while let Some(state) = stack.pop() {
+            let node = mapping[&state];
+            let mut targets: FxHashMap<NodeIndex, Vec<(u8, u8)>> = FxHashMap::default();
+
+            for reached in e_closure[&state].iter().map(NodeIndex::new) {
+                if self.graph[reached] == State::Accepting {
+                    res.make_accepting(node);
+                }
+
+                for edge in self.graph.edges(reached) {
+                    if *edge.weight() != Transition::Empty {
+                        targets
+                            .entry(edge.target())
+                            .or_default()
+                            .extend(edge.weight().ranges());
+                    }
+                }
+            }
+
+            for (target, ranges) in targets {
+                let target_node = *mapping.entry(target).or_insert_with(|| {
+                    stack.push(target);
+                    res.add_state()
+                });
+
+                res.add_transition(node, target_node, Transition::from_ranges(ranges));
+            }
+        }
+
+        res
+    }
+
+    /// Drops the states that are unreachable from the start or cannot reach
+    /// an accepting state, keeping the start in any case.
+    pub fn trim(&self) -> Nfa {
+        let live = self.live_states();
+        let classes: Vec<Option<usize>> = self
+            .graph
+            .node_indices()
+            .scan(0, |count, state| {
+                let keep = state == self.start || live.contains(state.index());
+                let class = keep.then_some(*count);
+                *count += keep as usize;
+
+                Some(class)
+            })
+            .collect();
+
+        self.quotient(&classes)
+    }
+
+    /// Merges states that accept alike and move to the same states on every
+    /// byte, repeating as long as merges make more states alike. Expects no
+    /// epsilon transitions.
+    pub fn merge_equivalent_states(&self) -> Nfa {
+        let mut classes: Vec<usize> = (0..self.graph.node_count()).collect();
+        let mut count = classes.len();
+
+        loop {
+            let mut ids: FxHashMap<Signature, usize> = FxHashMap::default();
+
+            let merged: Vec<usize> = self
+                .graph
+                .node_indices()
+                .map(|state| {
+                    let mut targets: FxHashMap<usize, Vec<(u8, u8)>> = FxHashMap::default();
+
+                    for edge in self.graph.edges(state) {
+                        targets
+                            .entry(classes[edge.target().index()])
+                            .or_default()
+                            .extend(edge.weight().ranges());
+                    }
+
+                    let mut signature: Vec<(usize, Vec<(u8, u8)>)> = targets
+                        .into_iter()
+                        .map(|(class, ranges)| (class, merge_ranges(ranges)))
+                        .collect();
+                    signature.sort();
+
+                    let accepting = self.graph[state] == State::Accepting;
+                    let id = ids.len();
+                    *ids.entry((accepting, signature)).or_insert(id)
+                })
+                .collect();
+
+            classes = merged;
+
+            if ids.len() == count {
+                break;
+            }
+
+            count = ids.len();
+        }
+
+        let classes: Vec<Option<usize>> = classes.into_iter().map(Some).collect();
+        self.quotient(&classes)
+    }
+
+    /// NFA with a state for each class of states, `None` dropping the state
+    /// and its transitions. Parallel byte transitions are merged.
+    fn quotient(&self, classes: &[Option<usize>]) -> Nfa {
+        let mut res = Nfa::new();
+        let count = classes.iter().flatten().max().map_or(0, |max| max + 1);
+
+        for _ in 0..count {
+            res.add_state();
+        }
+
+        res.set_start(NodeIndex::new(classes[self.start.index()].unwrap()));
+
+        let mut edges: FxHashMap<(usize, usize), Vec<(u8, u8)>> = FxHashMap::default();
+        let mut epsilons = Vec::new();
+
+        for state in self.graph.node_indices() {
+            let Some(from) = classes[state.index()] else {
+                continue;
+            };
+
+            if self.graph[state] == State::Accepting {
+                res.make_accepting(NodeIndex::new(from));
+            }
+
+            for edge in self.graph.edges(state) {
+                match (classes[edge.target().index()], edge.weight()) {
+                    (None, _) => {}
+                    (Some(to), Transition::Empty) => epsilons.push((from, to)),
+                    (Some(to), transition) => edges
+                        .entry((from, to))
+                        .or_default()
+                        .extend(transition.ranges()),
+                }
+            }
+        }
+
+        epsilons.sort();
+        epsilons.dedup();
+
+        for (from, to) in epsilons {
+            res.add_e_transition(NodeIndex::new(from), NodeIndex::new(to));
+        }
+
+        let mut edges: Vec<_> = edges.into_iter().collect();
+        edges.sort();
+
+        for ((from, to), ranges) in edges {
+            res.add_transition(
+                NodeIndex::new(from),
+                NodeIndex::new(to),
+                Transition::from_ranges(ranges),
+            );
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        nfa::Nfa,
+        parse,
+        testing::{assert_equivalent, PATTERNS},
+    };
+
+    #[test]
+    fn test_simplify() {
+        for pattern in ["(a|b)*c{3}"].iter().chain(PATTERNS) {
+            let nfa = Nfa::from_ast(&parse(pattern).unwrap());
+            let simplified = nfa.simplify();
+
+            assert_equivalent(&simplified.reduce_to_dfa(), &nfa.reduce_to_dfa(), pattern);
+            assert!(simplified.graph.node_count() <= nfa.graph.node_count());
+            assert!(simplified.graph.edge_count() <= nfa.graph.edge_count());
+        }
+
+        let nfa = Nfa::from_ast(&parse("(a|b)*c{3}").unwrap());
+        let simplified = nfa.simplify();
+
+        // The star collapses into the start, leaving one state per `c`
+        assert_eq!(simplified.graph.node_count(), 4);
+        assert_eq!(simplified.graph.edge_count(), 4);
+        assert!(nfa.graph.node_count() > 4 && nfa.graph.edge_count() > 4);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt::{Error, Write};
+
+use petgraph::visit::EdgeRef;
+
+use crate::{
+    escape::c_char,
+    nfa::{Nfa, State, Transition},
+    table::int_bits,
+};
+
+/// Words of a state set, as C initializer.
+fn words(states: impl Iterator<Item = usize>, count: usize) -> String {
+    let mut words = vec![0u64; count];
+
+    for state in states {
+        words[state / 64] |= 1 << (state % 64);
+    }
+
+    let words: Vec<String> = words.iter().map(|word| format!("0x{word:x}")).collect();
+    words.join(", ")
+}
+
+impl Nfa {
+    /// Emits C code that runs the NFA itself, keeping the set of active
+    /// states in a bitset, for automata whose DFA would be too large. Every
+    /// byte fires the transitions leaving active states and adds the epsilon
+    /// closures of their targets. The whole input has to match.
+    pub fn compile_simulation(&self) -> Result<String, Error> {
+        let count = self.graph.node_count();
+        let word_count = count.div_ceil(64);
+        let closure = self.e_closure();
+        let mut res = String::from("#include <stdint.h>\n#include <string.h>\n\n");
+
+        let accepting = self
+            .graph
+            .node_indices()
+            .filter(|&state| self.graph[state] == State::Accepting);
+
+        let edges: Vec<(usize, u8, u8, usize)> = self
+            .graph
+            .edge_references()
+            .filter(|edge| *edge.weight() != Transition::Empty)
+            .flat_map(|edge| {
+                let (from, to) = (edge.source().index(), edge.target().index());
+                edge.weight()
+                    .ranges()
+                    .into_iter()
+                    .map(move |(a, b)| (from, a, b, to))
+            })
+            .collect();
+
+        writeln!(&mut res, "#define WORDS {word_count}\n")?;
+        res.push_str("/* States reachable from each state through epsilon transitions */\n");
+        writeln!(
+            &mut res,
+            "static const uint64_t closure[{count}][WORDS] = {{"
+        )?;
+
+        for state in self.graph.node_indices() {
+            let states = closure.get(&state).unwrap().iter();
+            writeln!(&mut res, "\t{{{}}},", words(states, word_count))?;
+        }
+
+        res.push_str("};\n\n");
+        writeln!(
+            &mut res,
+            "static const uint64_t accepting[WORDS] = {{{}}};\n",
+            words(accepting.map(|state| state.index()), word_count)
+        )?;
+
+        // Only the empty input can match
+        if edges.is_empty() {
+            res.push_str("int matches(char *input) {\n");
+            res.push_str("\tint w;\n\n");
+            res.push_str("\tif (*input != '\\0')\n");
+            res.push_str("\t\treturn 0;\n\n");
+            res.push_str("\tfor (w = 0; w < WORDS; w++)\n");
+            writeln!(
+                &mut res,
+                "\t\tif (closure[{}][w] & accepting[w])",
+                self.start.index()
+            )?;
+            res.push_str("\t\t\treturn 1;\n\n");
+            res.push_str("\treturn 0;\n");
+            res.push_str("}\n");
+
+            return Ok(res);
+        }
+
+        let state_type = format!("uint{}_t", int_bits(count));
+
+        res.push_str("/* Byte transitions, fired on bytes from lo to hi */\n");
+        res.push_str("static const struct {\n");
+        writeln!(&mut res, "\t{state_type} from, to;")?;
+        res.push_str("\tunsigned char lo, hi;\n");
+        writeln!(&mut res, "}} edges[{}] = {{", edges.len())?;
+
+        for (from, a, b, to) in edges.iter() {
+            writeln!(
+                &mut res,
+                "\t{{{from}, {to}, {}, {}}},",
+                c_char(*a),
+                c_char(*b)
+            )?;
+        }
+
+        res.push_str("};\n\n");
+
+        res.push_str("int matches(char *input) {\n");
+        res.push_str("\tuint64_t states[WORDS], next[WORDS], any;\n");
+        res.push_str("\tunsigned char c;\n");
+        res.push_str("\tsize_t i;\n");
+        res.push_str("\tint w;\n\n");
+        writeln!(
+            &mut res,
+            "\tmemcpy(states, closure[{}], sizeof states);\n",
+            self.start.index()
+        )?;
+        res.push_str("\twhile ((c = *input++) != '\\0') {\n");
+        res.push_str("\t\tmemset(next, 0, sizeof next);\n\n");
+        res.push_str("\t\tfor (i = 0; i < sizeof edges / sizeof *edges; i++) {\n");
+        res.push_str("\t\t\tif (c < edges[i].lo || c > edges[i].hi)\n");
+        res.push_str("\t\t\t\tcontinue;\n\n");
+        res.push_str(
+            "\t\t\tif (((states[edges[i].from / 64] >> (edges[i].from % 64)) & 1) == 0)\n",
+        );
+        res.push_str("\t\t\t\tcontinue;\n\n");
+        res.push_str("\t\t\tfor (w = 0; w < WORDS; w++)\n");
+        res.push_str("\t\t\t\tnext[w] |= closure[edges[i].to][w];\n");
+        res.push_str("\t\t}\n\n");
+        res.push_str("\t\tany = 0;\n\n");
+        res.push_str("\t\tfor (w = 0; w < WORDS; w++)\n");
+        res.push_str("\t\t\tany |= states[w] = next[w];\n\n");
+        res.push_str("\t\tif (any == 0)\n");
+        res.push_str("\t\t\treturn 0;\n");
+        res.push_str("\t}\n\n");
+        res.push_str("\tfor (w = 0; w < WORDS; w++)\n");
+        res.push_str("\t\tif (states[w] & accepting[w])\n");
+        res.push_str("\t\t\treturn 1;\n\n");
+        res.push_str("\treturn 0;\n");
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{build, dfa::Dfa, glushkov::Glushkov, nfa::Nfa, parse, testing};
+
+    #[test]
+    fn test_simulation_against_dfa() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        let inputs: Vec<Vec<u8>> = testing::random_inputs(400)
+            .into_iter()
+            .filter(|input| !input.contains(&0))
+            .collect();
+        let mut patterns = Vec::new();
+        let mut sources = Vec::new();
+
+        // The NFAs of both constructions, the Glushkov one lacking & and ~
+        for pattern in testing::PATTERNS {
+            let ast = parse(pattern).unwrap();
+            let glushkov = Glushkov::new(&ast).map(|glushkov| Nfa::from_glushkov(&glushkov));
+
+            for nfa in [Some(Nfa::from_ast(&ast)), glushkov].into_iter().flatten() {
+                patterns.push(pattern);
+                sources.push(nfa.simplify().compile_simulation().unwrap());
+            }
+        }
+
+        let outputs = testing::run_c_matches("simulation", &sources, &inputs);
+
+        for (pattern, output) in patterns.into_iter().zip(outputs) {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+            assert_eq!(output, testing::verdicts(&dfa, &inputs), "{pattern}");
+        }
+    }
+}
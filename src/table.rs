@@ -0,0 +1,333 @@
+use std::fmt::{Error, Write};
+
+use crate::{analysis::Literals, dfa::Dfa, escape::c_char};
+
+/// Width of the smallest unsigned integer able to hold every value below `n`.
+pub fn int_bits(n: usize) -> u32 {
+    if n <= 1 << 8 {
+        8
+    } else if n <= 1 << 16 {
+        16
+    } else {
+        32
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(T::to_string).collect();
+    values.join(", ")
+}
+
+// The table as lists of numbers, shared by the table-driven backends so
+// that every target reads the very same automaton.
+impl Dfa {
+    /// Class of every byte, 16 bytes per row.
+    pub fn class_rows(&self) -> Vec<String> {
+        self.class_map.chunks(16).map(join).collect()
+    }
+
+    /// Next state of every class, one row per state.
+    pub fn transition_rows(&self) -> Vec<String> {
+        self.transitions
+            .chunks(self.class_count)
+            .map(join)
+            .collect()
+    }
+
+    /// Whether each state is accepting, as 0 or 1.
+    pub fn accepting_list(&self) -> String {
+        let flags: Vec<u8> = self.accepting.iter().map(|&a| a as u8).collect();
+        join(&flags)
+    }
+
+    /// Emits C code that runs the transition table in a loop, as an
+    /// alternative to `Nfa::compile`. Unlike the goto code the whole input
+    /// has to match.
+    pub fn compile_table(&self) -> Result<String, Error> {
+        let state_type = self.c_state_type();
+        let mut res = String::from("#include <stdint.h>\n\n");
+
+        self.c_tables(&mut res)?;
+
+        res.push_str("int matches(char *input) {\n");
+        writeln!(&mut res, "\t{state_type} state = {};", self.start)?;
+        res.push_str("\tunsigned char c;\n\n");
+        res.push_str("\twhile ((c = *input++) != '\\0')\n");
+        res.push_str("\t\tstate = transitions[state][classes[c]];\n\n");
+        res.push_str("\treturn accepting[state];\n");
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+
+    /// Emits C code defining `long search(const unsigned char *buf, size_t
+    /// len)`, the offset of the leftmost match in `buf` or -1. Positions
+    /// where no match can start according to `literals` are skipped without
+    /// running the automaton: with `memchr` on the rarest byte of a literal
+    /// prefix, or by testing the first byte of a match.
+    pub fn compile_search(&self, literals: &Literals) -> Result<String, Error> {
+        let state_type = self.c_state_type();
+        let live: Vec<u8> = self.live_states().into_iter().map(u8::from).collect();
+        let mut res =
+            String::from("#include <stddef.h>\n#include <stdint.h>\n#include <string.h>\n\n");
+
+        self.c_tables(&mut res)?;
+        writeln!(
+            &mut res,
+            "static const uint8_t live[{}] = {{{}}};\n",
+            self.state_count(),
+            join(&live)
+        )?;
+
+        res.push_str("/* Whether a match starts at buf + i */\n");
+        res.push_str("static int match_at(const unsigned char *buf, size_t len, size_t i) {\n");
+        writeln!(&mut res, "\t{state_type} state = {};\n", self.start)?;
+        res.push_str("\twhile (!accepting[state]) {\n");
+        res.push_str("\t\tif (i == len || !live[state])\n");
+        res.push_str("\t\t\treturn 0;\n\n");
+        res.push_str("\t\tstate = transitions[state][classes[buf[i++]]];\n");
+        res.push_str("\t}\n\n");
+        res.push_str("\treturn 1;\n");
+        res.push_str("}\n\n");
+
+        match (literals.rare_byte(), &literals.first_bytes) {
+            (Some((offset, rare)), _) => {
+                let prefix: Vec<String> = literals.prefix.iter().map(|&c| c_char(c)).collect();
+                let n = prefix.len();
+
+                if n > 1 {
+                    writeln!(
+                        &mut res,
+                        "static const unsigned char prefix[{n}] = {{{}}};\n",
+                        prefix.join(", ")
+                    )?;
+                }
+
+                res.push_str("long search(const unsigned char *buf, size_t len) {\n");
+                res.push_str("\tconst unsigned char *p;\n");
+                res.push_str("\tsize_t i = 0;\n\n");
+                writeln!(
+                    &mut res,
+                    "\t/* Every match starts with the prefix, holding {} at offset {offset} */",
+                    c_char(rare)
+                )?;
+                writeln!(&mut res, "\twhile (len - i >= {n}) {{")?;
+                writeln!(
+                    &mut res,
+                    "\t\tp = memchr(buf + i + {offset}, {}, len - i - {});",
+                    c_char(rare),
+                    n - 1
+                )?;
+                res.push_str("\t\tif (p == NULL)\n");
+                res.push_str("\t\t\treturn -1;\n\n");
+                writeln!(&mut res, "\t\ti = p - buf - {offset};\n")?;
+
+                if n > 1 {
+                    writeln!(
+                        &mut res,
+                        "\t\tif (memcmp(buf + i, prefix, {n}) == 0 && match_at(buf, len, i))"
+                    )?;
+                } else {
+                    res.push_str("\t\tif (match_at(buf, len, i))\n");
+                }
+
+                res.push_str("\t\t\treturn i;\n\n");
+                res.push_str("\t\ti++;\n");
+                res.push_str("\t}\n\n");
+                res.push_str("\treturn -1;\n");
+            }
+            (None, Some(first)) => {
+                let condition = if !first.is_empty() && first.len() <= 3 {
+                    let tests: Vec<String> = first
+                        .iter()
+                        .map(|&c| format!("buf[i] == {}", c_char(c)))
+                        .collect();
+                    format!("({})", tests.join(" || "))
+                } else {
+                    let mut flags = [0u8; 256];
+
+                    for &c in first {
+                        flags[c as usize] = 1;
+                    }
+
+                    res.push_str("/* Whether a match can start with each byte */\n");
+                    res.push_str("static const uint8_t first[256] = {\n");
+
+                    for row in flags.chunks(16) {
+                        writeln!(&mut res, "\t{},", join(row))?;
+                    }
+
+                    res.push_str("};\n\n");
+                    "first[buf[i]]".to_string()
+                };
+
+                res.push_str("long search(const unsigned char *buf, size_t len) {\n");
+                res.push_str("\tfor (size_t i = 0; i < len; i++) {\n");
+                writeln!(&mut res, "\t\tif ({condition} && match_at(buf, len, i))")?;
+                res.push_str("\t\t\treturn i;\n");
+                res.push_str("\t}\n\n");
+                res.push_str("\treturn -1;\n");
+            }
+            (None, None) => {
+                res.push_str("long search(const unsigned char *buf, size_t len) {\n");
+                res.push_str("\tfor (size_t i = 0; i <= len; i++) {\n");
+                res.push_str("\t\tif (match_at(buf, len, i))\n");
+                res.push_str("\t\t\treturn i;\n");
+                res.push_str("\t}\n\n");
+                res.push_str("\treturn -1;\n");
+            }
+        }
+
+        res.push_str("}\n");
+
+        Ok(res)
+    }
+
+    fn c_state_type(&self) -> String {
+        format!("uint{}_t", int_bits(self.state_count()))
+    }
+
+    /// The `classes`, `transitions` and `accepting` arrays in C.
+    fn c_tables(&self, res: &mut String) -> Result<(), Error> {
+        let state_type = self.c_state_type();
+
+        res.push_str("static const uint8_t classes[256] = {\n");
+
+        for row in self.class_rows() {
+            writeln!(res, "\t{row},")?;
+        }
+
+        res.push_str("};\n\n");
+
+        writeln!(
+            res,
+            "static const {state_type} transitions[{}][{}] = {{",
+            self.state_count(),
+            self.class_count
+        )?;
+
+        for row in self.transition_rows() {
+            writeln!(res, "\t{{{row}}},")?;
+        }
+
+        res.push_str("};\n\n");
+
+        writeln!(
+            res,
+            "static const uint8_t accepting[{}] = {{{}}};\n",
+            self.state_count(),
+            self.accepting_list()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt::Write, path::Path};
+
+    use crate::{analysis::Literals, build, dfa::Dfa, testing};
+
+    #[test]
+    fn test_table_against_dfa() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        let inputs: Vec<Vec<u8>> = testing::random_inputs(400)
+            .into_iter()
+            .filter(|input| !input.contains(&0))
+            .collect();
+        let dfas: Vec<Dfa> = testing::PATTERNS
+            .iter()
+            .map(|pattern| Dfa::from_nfa(&build(pattern).unwrap()))
+            .collect();
+        let sources: Vec<String> = dfas
+            .iter()
+            .map(|dfa| dfa.compile_table().unwrap())
+            .collect();
+        let outputs = testing::run_c_matches("table", &sources, &inputs);
+
+        for ((pattern, dfa), output) in testing::PATTERNS.iter().zip(&dfas).zip(outputs) {
+            assert_eq!(output, testing::verdicts(dfa, &inputs), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_search_against_dfa() {
+        if !testing::installed("cc") {
+            return;
+        }
+
+        let inputs = testing::random_inputs(400);
+        let mut harness = String::from("#include <stdio.h>\n");
+        harness.push_str(&testing::c_inputs(&inputs));
+        let mut paths = Vec::new();
+
+        for (i, pattern) in testing::PATTERNS.iter().enumerate() {
+            let min = build(pattern).unwrap();
+            let source = Dfa::from_nfa(&min)
+                .compile_search(&Literals::new(&min))
+                .unwrap();
+            let path = testing::scratch(&format!("search-{i}.c"));
+            std::fs::write(&path, format!("#define search search{i}\n{source}")).unwrap();
+            paths.push(path);
+
+            writeln!(
+                harness,
+                "long search{i}(const unsigned char *buf, size_t len);"
+            )
+            .unwrap();
+        }
+
+        harness.push_str("\nint main(void) {\n");
+        harness.push_str("\tsize_t j;\n\n");
+
+        for i in 0..testing::PATTERNS.len() {
+            harness.push_str("\tfor (j = 0; j < sizeof inputs / sizeof *inputs; j++)\n");
+            writeln!(
+                harness,
+                "\t\tprintf(\"%ld \", search{i}(inputs[j].bytes, inputs[j].len));"
+            )
+            .unwrap();
+            harness.push_str("\tputchar('\\n');\n");
+        }
+
+        harness.push_str("\n\treturn 0;\n}\n");
+
+        let path = testing::scratch("search-harness.c");
+        std::fs::write(&path, harness).unwrap();
+        paths.push(path);
+
+        let files: Vec<&Path> = paths.iter().map(|path| path.as_path()).collect();
+        let output = testing::run_c("search", &files);
+
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let output = output.unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), testing::PATTERNS.len());
+
+        for (pattern, line) in testing::PATTERNS.iter().zip(lines) {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+
+            // The leftmost offset where some prefix of the rest is matched
+            let expected: Vec<String> = inputs
+                .iter()
+                .map(|input| {
+                    (0..=input.len())
+                        .find(|&i| (i..=input.len()).any(|j| dfa.is_match(&input[i..j])))
+                        .map_or(-1, |i| i as i64)
+                        .to_string()
+                })
+                .collect();
+
+            assert_eq!(
+                line.split_whitespace().collect::<Vec<_>>(),
+                expected,
+                "{pattern}"
+            );
+        }
+    }
+}
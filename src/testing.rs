@@ -0,0 +1,226 @@
+//! Helpers for tests: running the generated code through external tools,
+//! which are skipped when the tool is not installed, comparing languages,
+//! and shared patterns and inputs.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    fmt::{Display, Write as _},
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{dfa::Dfa, nfa::Nfa};
+
+/// Whether `program` is on the PATH.
+pub fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// Path for a scratch file that is unique to the test process.
+pub fn scratch(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("regez-{}-{name}", std::process::id()))
+}
+
+/// Output of running a command, or the error output when it fails.
+pub fn run(command: &mut Command) -> Result<String, String> {
+    let output = command.output().unwrap();
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+    }
+}
+
+/// Compiles the C files `sources` into a program with warnings as errors,
+/// runs it and returns its output.
+pub fn run_c(name: &str, sources: &[&Path]) -> Result<String, String> {
+    let program = scratch(name);
+
+    run(Command::new("cc")
+        .args(["-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(&program)
+        .arg(format!("-I{}", env!("CARGO_MANIFEST_DIR")))
+        .args(sources))?;
+
+    let output = run(&mut Command::new(&program));
+    let _ = std::fs::remove_file(program);
+
+    output
+}
+
+/// C definitions of `inputs` as an array `inputs` of `bytes` and `len`
+/// pairs. The bytes are followed by a NUL so that inputs without NUL can
+/// also be passed as strings.
+pub fn c_inputs(inputs: &[Vec<u8>]) -> String {
+    let mut res = String::from("#include <stddef.h>\n\n");
+
+    // Every input starts with a padding byte so that no array is empty
+    for (i, input) in inputs.iter().enumerate() {
+        let bytes: Vec<String> = input.iter().chain(&[0]).map(u8::to_string).collect();
+        writeln!(
+            res,
+            "static const unsigned char input{i}[] = {{0, {}}};",
+            bytes.join(", ")
+        )
+        .unwrap();
+    }
+
+    res.push_str("\nstatic const struct {\n");
+    res.push_str("\tconst unsigned char *bytes;\n\tsize_t len;\n} inputs[] = {\n");
+
+    for (i, input) in inputs.iter().enumerate() {
+        writeln!(res, "\t{{input{i} + 1, {}}},", input.len()).unwrap();
+    }
+
+    res.push_str("};\n\n");
+    res
+}
+
+/// Compiles the C matchers `sources`, each defining `int matches(char
+/// *input)`, runs them on `inputs`, which must not hold NUL bytes, and
+/// returns the verdicts of each matcher as a string of `0` and `1`.
+pub fn run_c_matches(name: &str, sources: &[String], inputs: &[Vec<u8>]) -> Vec<String> {
+    let mut harness = String::from("#include <stdio.h>\n");
+    harness.push_str(&c_inputs(inputs));
+
+    let mut paths = Vec::new();
+
+    for (i, source) in sources.iter().enumerate() {
+        let path = scratch(&format!("{name}-{i}.c"));
+        std::fs::write(&path, format!("#define matches matches{i}\n{source}")).unwrap();
+        paths.push(path);
+
+        writeln!(harness, "int matches{i}(char *input);").unwrap();
+    }
+
+    let matchers: Vec<String> = (0..sources.len()).map(|i| format!("matches{i}")).collect();
+    writeln!(
+        harness,
+        "\nint (*const matchers[])(char *) = {{{}}};\n",
+        matchers.join(", ")
+    )
+    .unwrap();
+
+    harness.push_str("int main(void) {\n");
+    harness.push_str("\tsize_t i, j;\n\n");
+    harness.push_str("\tfor (i = 0; i < sizeof matchers / sizeof *matchers; i++) {\n");
+    harness.push_str("\t\tfor (j = 0; j < sizeof inputs / sizeof *inputs; j++)\n");
+    harness.push_str("\t\t\tputchar('0' + matchers[i]((char *)inputs[j].bytes));\n");
+    harness.push_str("\t\tputchar('\\n');\n");
+    harness.push_str("\t}\n\n");
+    harness.push_str("\treturn 0;\n}\n");
+
+    let path = scratch(&format!("{name}-harness.c"));
+    std::fs::write(&path, harness).unwrap();
+    paths.push(path);
+
+    let files: Vec<&Path> = paths.iter().map(|path| path.as_path()).collect();
+    let output = run_c(name, &files);
+
+    for path in paths {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    let outputs: Vec<String> = output.unwrap().lines().map(str::to_string).collect();
+    assert_eq!(outputs.len(), sources.len());
+
+    outputs
+}
+
+/// Verdicts of `dfa` on `inputs` as a string of `0` and `1`.
+pub fn verdicts(dfa: &Dfa, inputs: &[Vec<u8>]) -> String {
+    inputs
+        .iter()
+        .map(|input| match dfa.is_match(input) {
+            true => '1',
+            false => '0',
+        })
+        .collect()
+}
+
+/// Checks that `source` is valid C without any warning, returning the
+/// compiler output otherwise.
+pub fn check_c(source: &str) -> Result<(), String> {
+    let mut process = Command::new("cc")
+        .args([
+            "-fsyntax-only",
+            "-Wall",
+            "-Wextra",
+            "-Werror",
+            "-x",
+            "c",
+            "-",
+        ])
+        .arg(format!("-I{}", env!("CARGO_MANIFEST_DIR")))
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    process
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+
+    let output = process.wait_with_output().unwrap();
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+    }
+}
+
+/// Panics with one of the shortest strings telling them apart unless the
+/// deterministic automata `a` and `b` accept the same language.
+pub fn assert_equivalent(a: &Nfa, b: &Nfa, context: impl Display) {
+    let (a, b) = (Dfa::from_nfa(a), Dfa::from_nfa(b));
+
+    if let Some(witness) = a.product(&b, |x, y| x != y).shortest_match() {
+        let witness = String::from_utf8_lossy(&witness);
+        panic!("{context}: {witness:?} is matched by only one of them");
+    }
+}
+
+/// Patterns exercising every operator, used by the tests of the backends.
+pub const PATTERNS: &[&str] = &[
+    "()",
+    "[]",
+    "a",
+    "(a|b)*abb",
+    "x(ab|ac)*y?",
+    "[a-z]+@[a-z]+\\.(com|org)",
+    "(a|b)*a(a|b){3}",
+    "((ab)+|c?)*d{3}",
+    "(ab|c){1,3}d{2,}e{,2}",
+    "[a-z]+&~(if|else|while)",
+    "~(.*ab.*)",
+    "\\x00|\\xff+|'\\\\\"",
+];
+
+/// Random inputs made mostly of bytes occurring in `PATTERNS`, so that
+/// they get matched every now and then, along with some arbitrary bytes.
+pub fn random_inputs(count: usize) -> Vec<Vec<u8>> {
+    let alphabet = b"abcdefilorswxy@.";
+    let mut rng = StdRng::seed_from_u64(0);
+
+    (0..count)
+        .map(|_| {
+            let len = rng.gen_range(0..12);
+
+            (0..len)
+                .map(|_| match rng.gen_range(0..10) {
+                    0 => rng.gen(),
+                    _ => alphabet[rng.gen_range(0..alphabet.len())],
+                })
+                .collect()
+        })
+        .collect()
+}
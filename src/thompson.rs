@@ -0,0 +1,193 @@
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    ast::Ast,
+    determinize_within,
+    dfa::Dfa,
+    nfa::{Nfa, Transition, NFA_STATE_BUDGET},
+};
+
+impl Nfa {
+    pub fn from_ast(ast: &Ast) -> Nfa {
+        Nfa::from_ast_within(ast, usize::MAX).unwrap()
+    }
+
+    /// Thompson's construction giving up with `None` when `&` or `~` needs a
+    /// DFA of more than `max_states` states, or when a repetition would
+    /// expand past `max_states` copies or NFA states, or `NFA_STATE_BUDGET`
+    /// if that is more.
+    pub fn from_ast_within(ast: &Ast, max_states: usize) -> Option<Nfa> {
+        let mut nfa = Nfa::new();
+        let (start, end) = nfa.thompson(ast, max_states)?;
+        nfa.set_start(start);
+        nfa.make_accepting(end);
+        Some(nfa)
+    }
+
+    fn thompson(&mut self, ast: &Ast, max_states: usize) -> Option<(NodeIndex, NodeIndex)> {
+        let res = match ast {
+            Ast::Empty => {
+                let s1 = self.add_state();
+                let s2 = self.add_state();
+
+                self.add_e_transition(s1, s2);
+
+                (s1, s2)
+            }
+            Ast::Class(ranges) if ranges.is_empty() => (self.add_state(), self.add_state()),
+            Ast::Class(ranges) => {
+                let mut ranges = ranges.clone();
+
+                let mut conn1 = {
+                    let first_range = ranges.pop().unwrap();
+
+                    let s1 = self.add_state();
+                    let s2 = self.add_state();
+
+                    self.add_transition(s1, s2, Transition::Range(first_range.0, first_range.1));
+
+                    (s1, s2)
+                };
+
+                for range in ranges {
+                    let conn2 = {
+                        let s1 = self.add_state();
+                        let s2 = self.add_state();
+
+                        self.add_transition(s1, s2, Transition::Range(range.0, range.1));
+
+                        (s1, s2)
+                    };
+
+                    conn1 = self.union(conn1, conn2);
+                }
+
+                conn1
+            }
+            Ast::Concat(a, b) => {
+                let (start, end) = self.thompson(a, max_states)?;
+                let (s1, s2) = self.thompson(b, max_states)?;
+                self.add_e_transition(end, s1);
+
+                (start, s2)
+            }
+            Ast::Union(a, b) => {
+                let conn1 = self.thompson(a, max_states)?;
+                let conn2 = self.thompson(b, max_states)?;
+
+                self.union(conn1, conn2)
+            }
+            Ast::Intersection(a, b) => {
+                let a = determinize_within(a, max_states)?;
+                let b = determinize_within(b, max_states)?;
+                let (a, b) = (Dfa::from_nfa(&a), Dfa::from_nfa(&b));
+
+                self.embed(&a.intersection_within(&b, max_states)?.to_nfa())
+            }
+            Ast::Complement(a) => {
+                let a = determinize_within(a, max_states)?;
+                let a = Dfa::from_nfa(&a);
+
+                self.embed(&a.complement_within(max_states)?.to_nfa())
+            }
+            Ast::Star(a) => {
+                let conn = self.thompson(a, max_states)?;
+
+                let s1 = self.add_state();
+                let s2 = self.add_state();
+
+                self.add_e_transition(s1, conn.0);
+                self.add_e_transition(conn.1, s2);
+                self.add_e_transition(conn.1, conn.0);
+                self.add_e_transition(s1, s2);
+
+                (s1, s2)
+            }
+            Ast::Optional(a) => {
+                let conn = self.thompson(a, max_states)?;
+
+                let s1 = self.add_state();
+                let s2 = self.add_state();
+
+                self.add_e_transition(s1, s2);
+                self.add_e_transition(s1, conn.0);
+                self.add_e_transition(conn.1, s2);
+
+                (s1, s2)
+            }
+            Ast::Plus(a) => {
+                let conn = self.thompson(a, max_states)?;
+                let conn2 = self.clone_subgraph(conn.0, conn.1);
+
+                let s1 = self.add_state();
+                let s2 = self.add_state();
+
+                self.add_e_transition(conn.1, s1);
+                self.add_e_transition(s1, conn2.0);
+                self.add_e_transition(conn2.1, s2);
+                self.add_e_transition(conn2.1, conn2.0);
+                self.add_e_transition(s1, s2);
+
+                (conn.0, s2)
+            }
+            Ast::Repeat(_, n) if *n > max_states.max(NFA_STATE_BUDGET) as u64 => return None,
+            Ast::Repeat(a, n) => {
+                let conn = self.thompson(a, max_states)?;
+
+                let mut connector = conn.1;
+
+                for _ in 0..n - 1 {
+                    if self.graph.node_count() > max_states.max(NFA_STATE_BUDGET) {
+                        return None;
+                    }
+
+                    let new_conn = self.clone_subgraph(conn.0, conn.1);
+
+                    self.add_e_transition(connector, new_conn.0);
+                    connector = new_conn.1;
+                }
+
+                (conn.0, connector)
+            }
+        };
+
+        Some(res)
+    }
+
+    fn union(
+        &mut self,
+        conn1: (NodeIndex, NodeIndex),
+        conn2: (NodeIndex, NodeIndex),
+    ) -> (NodeIndex, NodeIndex) {
+        let s1 = self.add_state();
+        let s2 = self.add_state();
+
+        self.add_e_transition(s1, conn1.0);
+        self.add_e_transition(s1, conn2.0);
+        self.add_e_transition(conn1.1, s2);
+        self.add_e_transition(conn2.1, s2);
+
+        (s1, s2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{determinize_within, parse};
+
+    #[test]
+    fn test_repeat_budget() {
+        for pattern in ["a{99999}", "(a{1000}){1000}", "(a|b){5000}c{5000}"] {
+            let ast = parse(pattern).unwrap();
+            let start = Instant::now();
+
+            assert!(determinize_within(&ast, 10_000).is_none(), "{pattern}");
+            assert!(start.elapsed() < Duration::from_secs(1), "{pattern}");
+        }
+
+        let ast = parse("a{4000}").unwrap();
+        assert!(determinize_within(&ast, 10_000).is_some());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::nfa::{Nfa, Transition};
+
+impl Nfa {
+    /// Builds the trie of `literals` directly as a DFA, each state standing
+    /// for a prefix of some literal. Large keyword alternations avoid the
+    /// epsilon edges of Thompson's construction and the subset construction.
+    pub fn from_literals(literals: &[Vec<u8>]) -> Nfa {
+        Nfa::from_literals_within(literals, usize::MAX).unwrap()
+    }
+
+    /// Like `from_literals`, giving up with `None` once the trie would need
+    /// more than `max_states` states.
+    pub fn from_literals_within(literals: &[Vec<u8>], max_states: usize) -> Option<Nfa> {
+        if max_states == 0 {
+            return None;
+        }
+
+        let mut nfa = Nfa::new();
+        let root = nfa.add_state();
+        nfa.set_start(root);
+
+        for literal in literals {
+            let mut node = root;
+
+            for &c in literal {
+                node = match nfa.step(node, c) {
+                    Some(child) => child,
+                    None if nfa.graph.node_count() >= max_states => return None,
+                    None => {
+                        let child = nfa.add_state();
+                        nfa.add_transition(node, child, Transition::Range(c, c));
+                        child
+                    }
+                };
+            }
+
+            nfa.make_accepting(node);
+        }
+
+        Some(nfa)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{determinize_within, dfa::Dfa, nfa::Nfa, parse, testing::random_inputs};
+
+    #[test]
+    fn test_budget() {
+        let literals = [b"if".to_vec(), b"else".to_vec(), b"while".to_vec()];
+
+        // The root and one state for each of the 11 bytes
+        assert!(Nfa::from_literals_within(&literals, 12).is_some());
+        assert!(Nfa::from_literals_within(&literals, 11).is_none());
+
+        let keywords = parse("if|else|while").unwrap();
+        assert!(determinize_within(&keywords, 11).is_none());
+
+        let operand = parse("[a-z]+&~(if|else|while)").unwrap();
+        assert!(determinize_within(&operand, 11).is_none());
+    }
+
+    #[test]
+    fn test_operands() {
+        let dfa = determinize_within(&parse("[a-z]+&~(if|else|while)").unwrap(), 100).unwrap();
+        let dfa = Dfa::from_nfa(&dfa);
+
+        for input in random_inputs(1000) {
+            let keyword = [&b"if"[..], b"else", b"while"].contains(&&input[..]);
+            let word = !input.is_empty() && input.iter().all(u8::is_ascii_lowercase);
+
+            assert_eq!(dfa.is_match(&input), word && !keyword, "{input:?}");
+        }
+
+        for keyword in ["if", "else", "while"] {
+            assert!(!dfa.is_match(keyword.as_bytes()));
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::fmt::{Error, Write};
+
+use crate::dfa::Dfa;
+
+/// Condition on `data` for the bytes `a` to `b`.
+fn verilog_range(a: u8, b: u8) -> String {
+    match (a, b) {
+        _ if a == b => format!("data == 8'h{a:02x}"),
+        (0, _) => format!("data <= 8'h{b:02x}"),
+        (_, 255) => format!("data >= 8'h{a:02x}"),
+        _ => format!("data >= 8'h{a:02x} && data <= 8'h{b:02x}"),
+    }
+}
+
+impl Dfa {
+    /// Width of the state register.
+    fn state_width(&self) -> usize {
+        (usize::BITS - (self.state_count() - 1).leading_zeros()).max(1) as usize
+    }
+
+    /// Runs of bytes sharing the next state of `state`, covering 0 to 255.
+    fn runs(&self, state: usize) -> Vec<(u8, u8, usize)> {
+        let mut runs: Vec<(u8, u8, usize)> = Vec::new();
+
+        for c in 0..=255u8 {
+            let target = self.next(state, c);
+
+            match runs.last_mut() {
+                Some((_, b, last)) if *last == target => *b = c,
+                _ => runs.push((c, c, target)),
+            }
+        }
+
+        runs
+    }
+
+    /// Emits a synthesizable Verilog module named `name` running the DFA as
+    /// a binary-encoded FSM. It consumes `data` on every rising edge of
+    /// `clk` where `valid` is high, and `match` tells whether the bytes
+    /// consumed since `rst` are matched as a whole.
+    pub fn compile_verilog(&self, name: &str) -> Result<String, Error> {
+        let width = self.state_width();
+        let state = |s: usize| format!("{width}'d{s}");
+        let mut res = String::from("// Generated by regez, do not edit.\n\n");
+
+        writeln!(&mut res, "module {name} (")?;
+        res.push_str("    input wire clk,\n");
+        res.push_str("    input wire rst,\n");
+        res.push_str("    input wire valid,\n");
+        res.push_str("    input wire [7:0] data,\n");
+        res.push_str("    output wire match\n");
+        res.push_str(");\n");
+        writeln!(&mut res, "    reg [{}:0] state;\n", width - 1)?;
+
+        let accepting: Vec<String> = (0..self.state_count())
+            .filter(|&s| self.accepting[s])
+            .map(|s| format!("state == {}", state(s)))
+            .collect();
+
+        if accepting.is_empty() {
+            res.push_str("    assign match = 1'b0;\n\n");
+        } else {
+            writeln!(&mut res, "    assign match = {};\n", accepting.join(" || "))?;
+        }
+
+        res.push_str("    always @(posedge clk) begin\n");
+        res.push_str("        if (rst)\n");
+        writeln!(&mut res, "            state <= {};", state(self.start))?;
+        res.push_str("        else if (valid)\n");
+        res.push_str("            case (state)\n");
+
+        for s in 0..self.state_count() {
+            let runs = self.runs(s);
+
+            // The target reached by the most bytes goes in the final else
+            let mut sizes = vec![0; self.state_count()];
+
+            for &(a, b, target) in runs.iter() {
+                sizes[target] += b as usize - a as usize + 1;
+            }
+
+            let fallthrough = (0..sizes.len()).max_by_key(|&t| sizes[t]).unwrap();
+            let mut targets: Vec<usize> = Vec::new();
+
+            for &(_, _, target) in runs.iter() {
+                if target != fallthrough && !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+
+            writeln!(&mut res, "                {}:", state(s))?;
+
+            if targets.is_empty() {
+                writeln!(
+                    &mut res,
+                    "                    state <= {};",
+                    state(fallthrough)
+                )?;
+                continue;
+            }
+
+            for (i, &target) in targets.iter().enumerate() {
+                let conditions: Vec<String> = runs
+                    .iter()
+                    .filter(|&&(_, _, t)| t == target)
+                    .map(|&(a, b, _)| verilog_range(a, b))
+                    .collect();
+                let conditions = if conditions.len() == 1 {
+                    conditions[0].clone()
+                } else {
+                    let conditions: Vec<String> =
+                        conditions.iter().map(|c| format!("({c})")).collect();
+                    conditions.join(" || ")
+                };
+                let keyword = if i == 0 { "if" } else { "else if" };
+
+                writeln!(&mut res, "                    {keyword} ({conditions})")?;
+                writeln!(
+                    &mut res,
+                    "                        state <= {};",
+                    state(target)
+                )?;
+            }
+
+            res.push_str("                    else\n");
+            writeln!(
+                &mut res,
+                "                        state <= {};",
+                state(fallthrough)
+            )?;
+        }
+
+        // Encodings left unused by the states restart the match
+        if self.state_count() < 1 << width {
+            res.push_str("                default:\n");
+            writeln!(
+                &mut res,
+                "                    state <= {};",
+                state(self.start)
+            )?;
+        }
+
+        res.push_str("            endcase\n");
+        res.push_str("    end\n");
+        res.push_str("endmodule\n");
+
+        Ok(res)
+    }
+
+    /// Emits a testbench for the module of `compile_verilog`, feeding it the
+    /// first `n` accepted and `n` rejected strings over `alphabet` in
+    /// shortlex order and checking `match` after each of them.
+    pub fn compile_verilog_testbench(
+        &self,
+        name: &str,
+        alphabet: &[u8],
+        n: usize,
+    ) -> Result<String, Error> {
+        let rejecting = self.complement();
+        let accepted = self.shortlex(alphabet).take(n).map(|s| (s, true));
+        let rejected = rejecting.shortlex(alphabet).take(n).map(|s| (s, false));
+        let mut res = String::new();
+
+        writeln!(&mut res, "module {name}_tb;")?;
+        res.push_str("    reg clk = 0;\n");
+        res.push_str("    reg rst = 0;\n");
+        res.push_str("    reg valid = 0;\n");
+        res.push_str("    reg [7:0] data = 0;\n");
+        res.push_str("    wire match;\n");
+        res.push_str("    integer errors = 0;\n\n");
+        writeln!(
+            &mut res,
+            "    {name} dut (.clk(clk), .rst(rst), .valid(valid), .data(data), .match(match));\n"
+        )?;
+        res.push_str("    always #5 clk = ~clk;\n\n");
+
+        res.push_str("    task restart;\n");
+        res.push_str("        begin\n");
+        res.push_str("            rst = 1;\n");
+        res.push_str("            @(posedge clk) #1 rst = 0;\n");
+        res.push_str("        end\n");
+        res.push_str("    endtask\n\n");
+
+        res.push_str("    task feed(input [7:0] c);\n");
+        res.push_str("        begin\n");
+        res.push_str("            data = c;\n");
+        res.push_str("            valid = 1;\n");
+        res.push_str("            @(posedge clk) #1 valid = 0;\n");
+        res.push_str("        end\n");
+        res.push_str("    endtask\n\n");
+
+        res.push_str("    task check(input integer example, input expected);\n");
+        res.push_str("        if (match !== expected) begin\n");
+        res.push_str(
+            "            $display(\"example %0d: expected match = %b\", example, expected);\n",
+        );
+        res.push_str("            errors = errors + 1;\n");
+        res.push_str("        end\n");
+        res.push_str("    endtask\n\n");
+
+        res.push_str("    initial begin\n");
+
+        for (i, (string, expected)) in accepted.chain(rejected).enumerate() {
+            let escaped: Vec<u8> = string
+                .iter()
+                .flat_map(|&c| std::ascii::escape_default(c))
+                .collect();
+
+            writeln!(
+                &mut res,
+                "        // \"{}\"",
+                String::from_utf8(escaped).unwrap()
+            )?;
+            res.push_str("        restart;\n");
+
+            for c in string {
+                writeln!(&mut res, "        feed(8'h{c:02x});")?;
+            }
+
+            writeln!(&mut res, "        check({i}, 1'b{});", expected as u8)?;
+        }
+
+        res.push_str("        if (errors == 0)\n");
+        res.push_str("            $display(\"PASS\");\n");
+        res.push_str("        else\n");
+        res.push_str("            $display(\"FAIL: %0d errors\", errors);\n");
+        res.push_str("        $finish;\n");
+        res.push_str("    end\n");
+        res.push_str("endmodule\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::{build, dfa::Dfa, testing};
+
+    fn dfa(pattern: &str) -> Dfa {
+        Dfa::from_nfa(&build(pattern).unwrap())
+    }
+
+    /// Value of a `{width}'d{value}` literal.
+    fn literal(s: &str, width: usize) -> usize {
+        let prefix = format!("{width}'d");
+        s.trim_end_matches([';', ':'])
+            .strip_prefix(&prefix)
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    /// Evaluates a condition of the `if` chains on `data`.
+    fn holds(condition: &str, data: u8) -> bool {
+        let byte = |s: &str| u8::from_str_radix(s.strip_prefix("8'h").unwrap(), 16).unwrap();
+
+        condition.split(" || ").any(|term| {
+            let term = term.trim_start_matches('(').trim_end_matches(')');
+
+            term.split(" && ")
+                .all(|test| match test.split(' ').collect::<Vec<_>>()[..] {
+                    ["data", "==", c] => data == byte(c),
+                    ["data", "<=", c] => data <= byte(c),
+                    ["data", ">=", c] => data >= byte(c),
+                    _ => panic!("unexpected condition {test}"),
+                })
+        })
+    }
+
+    /// Next state of the FSM on `data`, read back from its `case` arm.
+    fn next(verilog: &str, width: usize, state: usize, data: u8) -> usize {
+        let arm = format!("{width}'d{state}:");
+        let mut lines = verilog
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != arm)
+            .skip(1);
+
+        loop {
+            let line = lines.next().unwrap();
+            let target = |lines: &mut dyn Iterator<Item = &str>| {
+                literal(
+                    lines.next().unwrap().strip_prefix("state <= ").unwrap(),
+                    width,
+                )
+            };
+
+            if let Some(target) = line.strip_prefix("state <= ") {
+                return literal(target, width);
+            } else if line == "else" {
+                return target(&mut lines);
+            }
+
+            let condition = line
+                .trim_start_matches("else ")
+                .strip_prefix("if (")
+                .unwrap()
+                .strip_suffix(')')
+                .unwrap();
+            let target = target(&mut lines);
+
+            if holds(condition, data) {
+                return target;
+            }
+        }
+    }
+
+    #[test]
+    fn test_state_width() {
+        for (pattern, states, width) in [
+            ("[\\x00-\\xff]*", 1, 1),
+            ("[]", 2, 1),
+            ("a", 3, 2),
+            ("ab*c", 4, 2),
+            ("abcd", 6, 3),
+        ] {
+            let dfa = dfa(pattern);
+            let verilog = dfa.compile_verilog("m").unwrap();
+
+            assert_eq!(dfa.state_count(), states, "{pattern}");
+            assert_eq!(dfa.state_width(), width, "{pattern}");
+            assert!(verilog.contains(&format!("reg [{}:0] state;", width - 1)));
+        }
+    }
+
+    #[test]
+    fn test_default_arm() {
+        for pattern in testing::PATTERNS {
+            let dfa = dfa(pattern);
+            let verilog = dfa.compile_verilog("m").unwrap();
+            let width = dfa.state_width();
+            let restart = format!(
+                "default:\n                    state <= {width}'d{};",
+                dfa.start
+            );
+
+            assert_eq!(
+                verilog.contains(&restart),
+                !dfa.state_count().is_power_of_two(),
+                "{pattern}"
+            );
+            assert_eq!(
+                verilog.matches("default:").count(),
+                verilog.matches(&restart).count()
+            );
+        }
+    }
+
+    #[test]
+    fn test_match_output() {
+        for pattern in testing::PATTERNS {
+            let dfa = dfa(pattern);
+            let verilog = dfa.compile_verilog("m").unwrap();
+            let width = dfa.state_width();
+
+            let assign = verilog
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("assign match = "))
+                .unwrap()
+                .strip_suffix(';')
+                .unwrap();
+
+            let accepting: Vec<usize> = match assign {
+                "1'b0" => Vec::new(),
+                _ => assign
+                    .split(" || ")
+                    .map(|test| literal(test.strip_prefix("state == ").unwrap(), width))
+                    .collect(),
+            };
+
+            let expected: Vec<usize> = (0..dfa.state_count())
+                .filter(|&s| dfa.accepting[s])
+                .collect();
+            assert_eq!(accepting, expected, "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_transitions() {
+        for pattern in testing::PATTERNS {
+            let dfa = dfa(pattern);
+            let verilog = dfa.compile_verilog("m").unwrap();
+
+            for state in 0..dfa.state_count() {
+                for data in 0..=255 {
+                    let next = next(&verilog, dfa.state_width(), state, data);
+                    assert_eq!(next, dfa.next(state, data), "{pattern}: {state} on {data}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_iverilog() {
+        if !testing::installed("iverilog") || !testing::installed("vvp") {
+            return;
+        }
+
+        let alphabet: Vec<u8> = b"abcdefilorswxy@.".to_vec();
+
+        for (i, pattern) in testing::PATTERNS.iter().enumerate() {
+            let dfa = dfa(pattern);
+            let name = format!("m{i}");
+            let source = testing::scratch(&format!("{name}.v"));
+            let program = testing::scratch(&format!("{name}.vvp"));
+
+            let module = dfa.compile_verilog(&name).unwrap();
+            let testbench = dfa.compile_verilog_testbench(&name, &alphabet, 16).unwrap();
+            std::fs::write(&source, format!("{module}\n{testbench}")).unwrap();
+
+            let output = testing::run(
+                Command::new("iverilog")
+                    .arg("-o")
+                    .arg(&program)
+                    .arg(&source),
+            )
+            .and_then(|_| testing::run(Command::new("vvp").arg(&program)));
+
+            std::fs::remove_file(source).unwrap();
+            let _ = std::fs::remove_file(program);
+
+            let output = output.unwrap_or_else(|err| panic!("{pattern}:\n{err}"));
+            assert!(output.contains("PASS"), "{pattern}:\n{output}");
+        }
+    }
+}
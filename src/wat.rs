@@ -0,0 +1,300 @@
+use std::fmt::{Error, Write};
+
+use crate::dfa::Dfa;
+
+impl Dfa {
+    /// Emits a WebAssembly text module exporting its memory and
+    /// `is_match(ptr, len) -> i32`, which tells whether the whole input is
+    /// matched. A first `br_table` maps the byte to its class and a second
+    /// one, indexed by state and class, jumps to the next state.
+    pub fn compile_wat(&self) -> Result<String, Error> {
+        let live = self.live_states();
+        let mut res = String::from("(module\n");
+
+        res.push_str("  (memory (export \"memory\") 1)\n");
+        res.push_str(
+            "  (func (export \"is_match\") (param $ptr i32) (param $len i32) (result i32)\n",
+        );
+        res.push_str("    (local $end i32)\n");
+        res.push_str("    (local $state i32)\n");
+        res.push_str("    (local $class i32)\n");
+        res.push_str("    local.get $ptr\n");
+        res.push_str("    local.get $len\n");
+        res.push_str("    i32.add\n");
+        res.push_str("    local.set $end\n");
+        writeln!(&mut res, "    i32.const {}", self.start)?;
+        res.push_str("    local.set $state\n");
+        res.push_str("    block $done\n");
+        res.push_str("      loop $next\n");
+        res.push_str("        local.get $ptr\n");
+        res.push_str("        local.get $end\n");
+        res.push_str("        i32.eq\n");
+        res.push_str("        br_if $done\n");
+
+        // Class of the byte, each block setting the class it is named after
+        res.push_str("        block $classified\n");
+
+        for class in (0..self.class_count).rev() {
+            writeln!(&mut res, "        block $c{class}")?;
+        }
+
+        res.push_str("        local.get $ptr\n");
+        res.push_str("        i32.load8_u\n");
+        let labels: Vec<String> = self.class_map.iter().map(|c| format!("$c{c}")).collect();
+        writeln!(&mut res, "        br_table {}", labels.join(" "))?;
+
+        for class in 0..self.class_count {
+            res.push_str("        end\n");
+            writeln!(&mut res, "        i32.const {class}")?;
+            res.push_str("        local.set $class\n");
+
+            if class + 1 < self.class_count {
+                res.push_str("        br $classified\n");
+            }
+        }
+
+        res.push_str("        end\n");
+        res.push_str("        local.get $ptr\n");
+        res.push_str("        i32.const 1\n");
+        res.push_str("        i32.add\n");
+        res.push_str("        local.set $ptr\n");
+
+        // Next state, transitions to dead states leaving the loop
+        let targets: Vec<usize> = (0..self.state_count()).filter(|&s| live[s]).collect();
+
+        res.push_str("        block $reject\n");
+
+        for target in targets.iter().rev() {
+            writeln!(&mut res, "        block $s{target}")?;
+        }
+
+        res.push_str("        local.get $state\n");
+        writeln!(&mut res, "        i32.const {}", self.class_count)?;
+        res.push_str("        i32.mul\n");
+        res.push_str("        local.get $class\n");
+        res.push_str("        i32.add\n");
+
+        let labels: Vec<String> = self
+            .transitions
+            .iter()
+            .map(|&target| {
+                if live[target] {
+                    format!("$s{target}")
+                } else {
+                    "$reject".to_string()
+                }
+            })
+            .chain(["$reject".to_string()])
+            .collect();
+        writeln!(&mut res, "        br_table {}", labels.join(" "))?;
+
+        for target in targets.iter() {
+            res.push_str("        end\n");
+            writeln!(&mut res, "        i32.const {target}")?;
+            res.push_str("        local.set $state\n");
+            res.push_str("        br $next\n");
+        }
+
+        res.push_str("        end\n");
+        res.push_str("        i32.const 0\n");
+        res.push_str("        return\n");
+        res.push_str("      end\n");
+        res.push_str("    end\n");
+
+        // Whether the state reached at the end of the input is accepting
+        res.push_str("    block $rejected\n");
+        res.push_str("    block $accepted\n");
+        res.push_str("    local.get $state\n");
+
+        let labels: Vec<&str> = self
+            .accepting
+            .iter()
+            .map(|&accepting| if accepting { "$accepted" } else { "$rejected" })
+            .chain(["$rejected"])
+            .collect();
+        writeln!(&mut res, "    br_table {}", labels.join(" "))?;
+
+        res.push_str("    end\n");
+        res.push_str("    i32.const 1\n");
+        res.push_str("    return\n");
+        res.push_str("    end\n");
+        res.push_str("    i32.const 0\n");
+        res.push_str("  )\n");
+        res.push_str(")\n");
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use rustc_hash::FxHashMap;
+
+    use crate::{build, dfa::Dfa, testing};
+
+    /// Instructions of the function body, one per line.
+    fn instructions(wat: &str) -> Vec<Vec<&str>> {
+        wat.lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('(') && !line.starts_with(')'))
+            .map(|line| line.split_whitespace().collect())
+            .collect()
+    }
+
+    /// Runs `is_match` on `input` with just enough of a WebAssembly
+    /// interpreter for the instructions the backend emits.
+    fn interpret(body: &[Vec<&str>], input: &[u8]) -> i64 {
+        // Matching `end` of every block and loop
+        let mut ends = FxHashMap::default();
+        let mut open = Vec::new();
+
+        for (pc, instruction) in body.iter().enumerate() {
+            match instruction[0] {
+                "block" | "loop" => open.push(pc),
+                "end" => {
+                    ends.insert(open.pop().unwrap(), pc);
+                }
+                _ => {}
+            }
+        }
+
+        let mut locals: FxHashMap<&str, i64> =
+            FxHashMap::from_iter([("$ptr", 0), ("$len", input.len() as i64)]);
+        let mut stack: Vec<i64> = Vec::new();
+        let mut labels: Vec<(&str, usize)> = Vec::new();
+        let mut pc = 0;
+
+        while pc < body.len() {
+            let instruction = &body[pc];
+            let mut target = None;
+
+            match instruction[0] {
+                "block" | "loop" => labels.push((instruction[1], pc)),
+                "end" => {
+                    labels.pop();
+                }
+                "local.get" => stack.push(*locals.get(instruction[1]).unwrap_or(&0)),
+                "local.set" => {
+                    locals.insert(instruction[1], stack.pop().unwrap());
+                }
+                "i32.const" => stack.push(instruction[1].parse().unwrap()),
+                "i32.load8_u" => {
+                    let ptr = stack.pop().unwrap();
+                    stack.push(input[ptr as usize] as i64);
+                }
+                "i32.add" | "i32.mul" | "i32.eq" => {
+                    let (b, a) = (stack.pop().unwrap(), stack.pop().unwrap());
+
+                    stack.push(match instruction[0] {
+                        "i32.add" => a + b,
+                        "i32.mul" => a * b,
+                        _ => (a == b) as i64,
+                    });
+                }
+                "br" => target = Some(instruction[1]),
+                "br_if" if stack.pop().unwrap() != 0 => target = Some(instruction[1]),
+                "br_if" => {}
+                "br_table" => {
+                    let index = stack.pop().unwrap() as usize;
+                    target = Some(instruction[(index + 1).min(instruction.len() - 1)]);
+                }
+                "return" => break,
+                other => panic!("unexpected instruction {other}"),
+            }
+
+            pc = match target {
+                None => pc + 1,
+                Some(target) => {
+                    while labels.last().unwrap().0 != target {
+                        labels.pop();
+                    }
+
+                    let (_, start) = labels.last().copied().unwrap();
+
+                    // Branching to a loop restarts it, to a block leaves it
+                    if body[start][0] == "loop" {
+                        start + 1
+                    } else {
+                        labels.pop();
+                        ends[&start] + 1
+                    }
+                }
+            };
+        }
+
+        stack.pop().unwrap()
+    }
+
+    #[test]
+    fn test_structure() {
+        for pattern in testing::PATTERNS {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+            let wat = dfa.compile_wat().unwrap();
+            let body = instructions(&wat);
+
+            assert!(wat.contains("(memory (export \"memory\") 1)"));
+            assert!(wat.contains(
+                "(func (export \"is_match\") (param $ptr i32) (param $len i32) (result i32)"
+            ));
+
+            let blocks = body.iter().filter(|i| i[0] == "block" || i[0] == "loop");
+            let ends = body.iter().filter(|i| i[0] == "end");
+            assert_eq!(blocks.count(), ends.count(), "{pattern}");
+
+            let tables: Vec<&Vec<&str>> = body.iter().filter(|i| i[0] == "br_table").collect();
+            assert_eq!(tables.len(), 3, "{pattern}");
+
+            // One label per byte, the last one being the default
+            let classes: Vec<String> = dfa.class_map.iter().map(|c| format!("$c{c}")).collect();
+            assert_eq!(tables[0][1..], classes, "{pattern}");
+
+            // Past the last state, the defaults reject
+            assert_eq!(tables[1].len(), 1 + dfa.transitions.len() + 1, "{pattern}");
+            assert_eq!(tables[1].last(), Some(&"$reject"), "{pattern}");
+            assert_eq!(tables[2].len(), 1 + dfa.state_count() + 1, "{pattern}");
+            assert_eq!(tables[2].last(), Some(&"$rejected"), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_interpreted_against_dfa() {
+        let inputs = testing::random_inputs(300);
+
+        for pattern in testing::PATTERNS {
+            let dfa = Dfa::from_nfa(&build(pattern).unwrap());
+            let wat = dfa.compile_wat().unwrap();
+            let body = instructions(&wat);
+
+            for input in inputs.iter() {
+                let expected = dfa.is_match(input) as i64;
+                assert_eq!(interpret(&body, input), expected, "{pattern} on {input:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_wat2wasm() {
+        if !testing::installed("wat2wasm") {
+            return;
+        }
+
+        for (i, pattern) in testing::PATTERNS.iter().enumerate() {
+            let wat = Dfa::from_nfa(&build(pattern).unwrap())
+                .compile_wat()
+                .unwrap();
+            let (source, module) = (
+                testing::scratch(&format!("{i}.wat")),
+                testing::scratch(&format!("{i}.wasm")),
+            );
+            std::fs::write(&source, wat).unwrap();
+
+            let output = testing::run(Command::new("wat2wasm").arg(&source).arg("-o").arg(&module));
+
+            std::fs::remove_file(source).unwrap();
+            let _ = std::fs::remove_file(module);
+            output.unwrap_or_else(|err| panic!("{pattern}:\n{err}"));
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Runs regez with `args`, killing it unless it exits within thirty seconds,
+/// and returns its exit code and error output.
+fn regez(args: &[&str]) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_regex"))
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+
+        if start.elapsed() > Duration::from_secs(30) {
+            child.kill().unwrap();
+            panic!("regez {args:?} did not exit within thirty seconds");
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+
+    (status.code(), stderr)
+}
+
+#[test]
+fn test_shift_and_stops_at_max_positions() {
+    for pattern in ["a{100000000}", "a{1000000000}"] {
+        for fallback in ["--fallback=shift-and", "--fallback=auto"] {
+            let (code, _) = regez(&[fallback, pattern]);
+            assert_eq!(code, Some(2), "{fallback} {pattern}");
+        }
+
+        let (code, _) = regez(&["--codegen=shift-and", pattern]);
+        assert_eq!(code, Some(2), "{pattern}");
+    }
+}
+
+#[test]
+fn test_no_silent_fallback() {
+    let (code, stderr) = regez(&["(a|b)*a(a|b){15}"]);
+
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("--fallback"), "{stderr}");
+
+    let (code, _) = regez(&["--fallback=shift-and", "(a|b)*a(a|b){15}"]);
+    assert_eq!(code, Some(0));
+}
+
+#[test]
+fn test_max_states() {
+    let (code, _) = regez(&["--max-states", "0", "a"]);
+    assert_eq!(code, Some(2));
+
+    let (code, stderr) = regez(&["--search", "--max-states", "1", "ab"]);
+    assert_eq!(code, Some(2));
+    assert!(!stderr.contains("--fallback"), "{stderr}");
+
+    // The NFA is not held to --max-states
+    let (code, stderr) = regez(&["--fallback=nfa", "--max-states", "100", "(a|b)*a(a|b){70}"]);
+    assert_eq!(code, Some(0), "{stderr}");
+}
+
+#[test]
+fn test_fallback_conflicts() {
+    for flag in ["--search", "--streaming"] {
+        let (code, _) = regez(&[flag, "--fallback=nfa", "a"]);
+        assert_eq!(code, Some(2), "{flag}");
+    }
+
+    let (code, _) = regez(&["--target=rust", "--fallback=nfa", "a"]);
+    assert_eq!(code, Some(2));
+}
+
+#[test]
+fn test_products_within_budget() {
+    let (code, stderr) = regez(&["(.{97})*&(.{91})*"]);
+    assert_eq!(code, Some(0), "{stderr}");
+
+    let (code, _) = regez(&["(.{101})*&(.{103})*"]);
+    assert_eq!(code, Some(2));
+
+    let (code, _) = regez(&["equiv", "(.{31})*&(.{29})*", "(.{29})*&(.{31})*"]);
+    assert_eq!(code, Some(0));
+
+    let (code, stderr) = regez(&["equiv", "(.{101})*", "(.{103})*"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("comparing"), "{stderr}");
+}